@@ -1,92 +1,444 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct Root {
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{
+    de::{Error, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+
+use crate::{locate::Source, rules::Rule};
+
+/// Root of the YAML.
+#[derive(Serialize, Debug, Clone)]
+pub struct Root {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, GroupData>,
     characters: Vec<Character>,
+    #[serde(skip)]
+    sources: Vec<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct Character {
+/// Definition of a character (`\0`, `\1`, ...).
+#[derive(Serialize, Debug, Clone)]
+pub struct Character {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
     parts: Vec<GroupData>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
+    #[serde(skip)]
+    source: Option<Source>,
+    /// Whether any of `base`, `offset`, `parts` and `rules` is written, even if empty.
+    #[serde(skip)]
+    has_definitions: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct GroupData {
+/// Group of poses, such as "eyes" or "mouth".
+#[derive(Serialize, Debug, Clone)]
+pub struct GroupData {
+    #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+    library: Option<String>,
     group: String,
     details: Vec<PoseData>,
 }
 
+/// Pose in a group and its surface definition.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct PoseData {
+pub struct PoseData {
+    #[serde(deserialize_with = "deserialize_pose_name")]
     name: String,
     text: String,
 }
 
 impl Root {
-    #[cfg(test)]
+    /// Creates a new root.
     pub fn new(raw: Option<String>, characters: Vec<Character>) -> Root {
-        Root { raw, characters }
+        Root {
+            include: Vec::new(),
+            raw,
+            offset: None,
+            groups: BTreeMap::new(),
+            characters,
+            sources: Vec::new(),
+        }
     }
 
-    #[cfg(test)]
+    /// Sets the default interval of surface numbers between characters.
+    pub fn with_offset(mut self, offset: usize) -> Root {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Definitions output as is, such as `descript`.
     pub fn raw(&self) -> Option<&String> {
         self.raw.as_ref()
     }
 
+    /// Interval of surface numbers between characters.
+    /// If `None`, the power of ten above the largest surface number is used.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Characters in order of `\0`, `\1`, ...
     pub fn characters(&self) -> &Vec<Character> {
         &self.characters
     }
+
+    /// Groups shared by characters, referred to by `use` in their parts.
+    pub fn groups(&self) -> &BTreeMap<String, GroupData> {
+        &self.groups
+    }
+
+    pub(crate) fn with_groups(mut self, groups: BTreeMap<String, GroupData>) -> Root {
+        self.groups = groups;
+        self
+    }
+
+    pub(crate) fn characters_mut(&mut self) -> &mut Vec<Character> {
+        &mut self.characters
+    }
+
+    /// Files read to build the root, including the ones referenced by `include` and `file`.
+    pub fn sources(&self) -> &Vec<PathBuf> {
+        &self.sources
+    }
+
+    pub(crate) fn with_sources(mut self, sources: Vec<PathBuf>) -> Root {
+        self.sources = sources;
+        self
+    }
+
+    pub(crate) fn take_include(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.include)
+    }
 }
 
 impl Character {
-    #[cfg(test)]
+    /// Creates a new character.
     pub fn new(base: Option<String>, parts: Vec<GroupData>) -> Character {
-        Character { base, parts }
+        Character {
+            file: None,
+            base,
+            offset: None,
+            parts,
+            rules: Vec::new(),
+            source: None,
+            has_definitions: false,
+        }
     }
 
-    #[cfg(test)]
+    /// Sets the number added to all surfaces of the character.
+    pub fn with_offset(mut self, offset: usize) -> Character {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Definition common to all surfaces of the character.
     pub fn base(&self) -> Option<&String> {
         self.base.as_ref()
     }
 
+    /// Sets the constraints on combinations of poses.
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Character {
+        self.rules = rules;
+        self
+    }
+
+    /// Number added to all surfaces of the character.
+    /// If `None`, the index of the character times [`Root::offset`] is used.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Groups combined into surfaces.
     pub fn parts(&self) -> &Vec<GroupData> {
         &self.parts
     }
+
+    /// Constraints on combinations of poses.
+    pub fn rules(&self) -> &Vec<Rule> {
+        &self.rules
+    }
+
+    pub(crate) fn parts_mut(&mut self) -> &mut Vec<GroupData> {
+        &mut self.parts
+    }
+
+    pub(crate) fn has_definitions(&self) -> bool {
+        self.has_definitions
+    }
+
+    pub(crate) fn take_file(&mut self) -> Option<PathBuf> {
+        self.file.take()
+    }
+
+    /// File the character is defined in, if read from a file.
+    pub fn source(&self) -> Option<&PathBuf> {
+        self.source.as_ref().map(|v| v.path())
+    }
+
+    pub(crate) fn origin(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Records the file, and the index in its `characters` unless the file is the character.
+    pub(crate) fn with_source(mut self, path: Option<&Path>, index: Option<usize>) -> Character {
+        self.source = path.map(|v| Source::new(v, index));
+        self
+    }
 }
 
 impl GroupData {
-    #[cfg(test)]
+    /// Creates a new group.
     pub fn new(group: String, details: Vec<PoseData>) -> GroupData {
-        GroupData { group, details }
+        GroupData {
+            library: None,
+            group,
+            details,
+        }
     }
-    #[allow(dead_code)]
+
+    /// Name of the group.
     pub fn group(&self) -> &String {
         &self.group
     }
 
+    /// Poses in the group.
     pub fn details(&self) -> &Vec<PoseData> {
         &self.details
     }
+
+    pub(crate) fn take_library(&mut self) -> Option<String> {
+        self.library.take()
+    }
 }
 
 impl PoseData {
-    #[cfg(test)]
+    /// Creates a new pose.
     pub fn new(name: String, text: String) -> PoseData {
         PoseData { name, text }
     }
 
+    /// Name of the pose.
     pub fn name(&self) -> &String {
         &self.name
     }
 
-    #[cfg(test)]
+    /// Surface definition of the pose.
     pub fn text(&self) -> &String {
         &self.text
     }
 }
 
+impl<'de> Deserialize<'de> for Root {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &["include", "raw", "offset", "groups", "characters"];
+        deserializer.deserialize_struct("Root", FIELDS, RootVisitor)
+    }
+}
+
+/// Reads the root, where `characters` may be omitted if it includes other files.
+struct RootVisitor;
+
+impl<'de> Visitor<'de> for RootVisitor {
+    type Value = Root;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "struct Root")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Root, A::Error> {
+        let mut include = None;
+        let mut raw = None;
+        let mut offset = None;
+        let mut groups = None;
+        let mut characters = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "include" => next_field(&mut map, &mut include, "include")?,
+                "raw" => next_field(&mut map, &mut raw, "raw")?,
+                "offset" => next_field(&mut map, &mut offset, "offset")?,
+                "groups" => next_field(&mut map, &mut groups, "groups")?,
+                "characters" => next_field(&mut map, &mut characters, "characters")?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let include: Vec<PathBuf> = include.unwrap_or_default();
+        let characters = match characters {
+            Some(v) => v,
+            None if !include.is_empty() => Vec::new(),
+            None => return Err(A::Error::missing_field("characters")),
+        };
+        let groups: BTreeMap<String, LibraryGroup> = groups.unwrap_or_default();
+        Ok(Root {
+            include,
+            raw: raw.flatten(),
+            offset: offset.flatten(),
+            groups: groups.into_iter().map(|(key, v)| (key, v.0)).collect(),
+            characters,
+            sources: Vec::new(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Character {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &["file", "base", "offset", "parts", "rules"];
+        deserializer.deserialize_struct("Character", FIELDS, CharacterVisitor)
+    }
+}
+
+/// Reads a character, where `parts` may be omitted if it is read from `file`.
+struct CharacterVisitor;
+
+impl<'de> Visitor<'de> for CharacterVisitor {
+    type Value = Character;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "struct Character")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Character, A::Error> {
+        let mut file = None;
+        let mut base = None;
+        let mut offset = None;
+        let mut parts = None;
+        let mut rules = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "file" => next_field(&mut map, &mut file, "file")?,
+                "base" => next_field(&mut map, &mut base, "base")?,
+                "offset" => next_field(&mut map, &mut offset, "offset")?,
+                "parts" => next_field(&mut map, &mut parts, "parts")?,
+                "rules" => next_field(&mut map, &mut rules, "rules")?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let file: Option<PathBuf> = file.flatten();
+        let has_definitions =
+            base.is_some() || offset.is_some() || parts.is_some() || rules.is_some();
+        let parts = match parts {
+            Some(v) => v,
+            None if file.is_some() => Vec::new(),
+            None => return Err(A::Error::missing_field("parts")),
+        };
+        Ok(Character {
+            file,
+            base: base.flatten(),
+            offset: offset.flatten(),
+            parts,
+            rules: rules.unwrap_or_default(),
+            source: None,
+            has_definitions,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for GroupData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(GroupVisitor { in_library: false })
+    }
+}
+
+/// Group defined in `groups`, which cannot `use` another one and may omit its name.
+struct LibraryGroup(GroupData);
+
+impl<'de> Deserialize<'de> for LibraryGroup {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_map(GroupVisitor { in_library: true })
+            .map(LibraryGroup)
+    }
+}
+
+/// Reads a group, where `details` may be omitted if it uses a shared one.
+struct GroupVisitor {
+    in_library: bool,
+}
+
+impl<'de> Visitor<'de> for GroupVisitor {
+    type Value = GroupData;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "struct GroupData")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<GroupData, A::Error> {
+        let mut library = None;
+        let mut group = None;
+        let mut details = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "use" if self.in_library => {
+                    return Err(A::Error::custom("groups cannot use another group"));
+                }
+                "use" => next_field(&mut map, &mut library, "use")?,
+                "group" => next_field(&mut map, &mut group, "group")?,
+                "details" => next_field(&mut map, &mut details, "details")?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let group = match (group, &library) {
+            (Some(v), _) => v,
+            (None, _) if self.in_library => String::new(),
+            (None, Some(_)) => String::new(),
+            (None, None) => return Err(A::Error::missing_field("group")),
+        };
+        let details = match (details, &library) {
+            (Some(v), _) => v,
+            (None, Some(_)) => Vec::new(),
+            (None, None) => return Err(A::Error::missing_field("details")),
+        };
+        Ok(GroupData {
+            library,
+            group,
+            details,
+        })
+    }
+}
+
+/// Reads the value of a key into `field`, failing if the key appeared before.
+fn next_field<'de, A: MapAccess<'de>, T: Deserialize<'de>>(
+    map: &mut A,
+    field: &mut Option<T>,
+    name: &'static str,
+) -> Result<(), A::Error> {
+    if field.is_some() {
+        return Err(A::Error::duplicate_field(name));
+    }
+    *field = Some(map.next_value()?);
+    Ok(())
+}
+
+fn deserialize_pose_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(name) if !name.is_empty() => Ok(name),
+        _ => Err(D::Error::custom("pose has no name")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -190,7 +542,7 @@ characters:
           text: |
             element0,overlay,surface3001.png,0,0
 "#;
-            let result: Root = serde_yml::from_str(&case).unwrap();
+            let result: Root = serde_yml::from_str(case).unwrap();
             assert!(result.characters[1].base.is_none());
             assert_eq!(result.characters[1].parts[0].group, "素体");
         }
@@ -207,7 +559,7 @@ characters:
           - name: bbb
             text: |
 "#;
-            let result: Root = serde_yml::from_str(&case).unwrap();
+            let result: Root = serde_yml::from_str(case).unwrap();
             assert!(result.raw.is_none());
         }
 
@@ -220,7 +572,86 @@ characters:
     parts:
       - group:
 "#;
-            assert!(serde_yml::from_str::<Root>(&case).is_err());
+            assert!(serde_yml::from_str::<Root>(case).is_err());
+        }
+
+        #[test]
+        fn failed_when_pose_has_no_name() {
+            let case = r#"
+characters:
+  - parts:
+      - group: eyes
+        details:
+          - name:
+            text: |
+"#;
+            let result = serde_yml::from_str::<Root>(case).unwrap_err();
+            assert!(result.to_string().contains("pose has no name"));
+            assert_eq!(result.location().map(|v| v.line()), Some(6));
+        }
+
+        #[test]
+        fn success_when_included_or_from_file() {
+            let root = serde_yml::from_str::<Root>("include:\n  - common.yaml\n").unwrap();
+            assert!(root.characters().is_empty());
+
+            let root = serde_yml::from_str::<Root>("characters:\n  - file: sakura.yaml\n").unwrap();
+            assert!(root.characters()[0].parts().is_empty());
+        }
+
+        #[test]
+        fn failed_when_parts_is_missing() {
+            let case = r#"
+characters:
+  - base: |
+      element0,overlay,surface0.png,0,0
+"#;
+            let result = serde_yml::from_str::<Root>(case).unwrap_err();
+            assert!(result.to_string().contains("missing field `parts`"));
+            assert_eq!(result.location().map(|v| v.line()), Some(3));
+        }
+
+        #[test]
+        fn failed_when_group_is_missing() {
+            let case = r#"
+characters:
+  - parts:
+      - details:
+          - name: open
+            text: |
+"#;
+            let result = serde_yml::from_str::<Root>(case).unwrap_err();
+            assert!(result.to_string().contains("missing field `group`"));
+        }
+
+        #[test]
+        fn failed_when_key_is_duplicated() {
+            let case = r#"
+characters:
+  - parts:
+      - group: eyes
+        group: mouth
+        details: []
+"#;
+            let result = serde_yml::from_str::<Root>(case).unwrap_err();
+            assert!(result.to_string().contains("duplicate field `group`"));
+        }
+
+        #[test]
+        fn failed_when_library_uses_group() {
+            let case = r#"
+groups:
+  eyes:
+    use: mouth
+  mouth:
+    details: []
+characters: []
+"#;
+            let result = serde_yml::from_str::<Root>(case).unwrap_err();
+            assert!(result
+                .to_string()
+                .contains("groups cannot use another group"));
+            assert_eq!(result.location().map(|v| v.line()), Some(4));
         }
     }
 }
@@ -0,0 +1,170 @@
+use std::{fmt::Display, str::FromStr};
+
+use encoding_rs::EncoderResult;
+
+use crate::error::ProcessError;
+
+/// Character encoding of the generated files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// Shift_JIS, encoded as CP932 (Windows-31J).
+    ShiftJis,
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Charset::Utf8),
+            "shift-jis" | "sjis" | "cp932" | "windows-31j" => Ok(Charset::ShiftJis),
+            _ => Err(format!(
+                "Charset is invalid: {}: expected UTF-8 or Shift_JIS",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Charset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Charset {
+    /// Name written in the `charset` line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::ShiftJis => "Shift_JIS",
+        }
+    }
+
+    /// Decodes `bytes`, replacing invalid sequences.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let encoding = match self {
+            Charset::Utf8 => encoding_rs::UTF_8,
+            Charset::ShiftJis => encoding_rs::SHIFT_JIS,
+        };
+
+        encoding.decode(bytes).0.into_owned()
+    }
+
+    /// Encodes `contents`, failing on characters which cannot be represented.
+    pub fn encode(&self, contents: &str) -> Result<Vec<u8>, ProcessError> {
+        let encoding = match self {
+            Charset::Utf8 => return Ok(contents.as_bytes().to_vec()),
+            Charset::ShiftJis => encoding_rs::SHIFT_JIS,
+        };
+
+        let mut encoder = encoding.new_encoder();
+        let mut bytes = Vec::new();
+        let mut position = 0;
+        loop {
+            let rest = &contents[position..];
+            let additional = encoder
+                .max_buffer_length_from_utf8_without_replacement(rest.len())
+                .unwrap_or(rest.len());
+            bytes.reserve(additional);
+            let (result, read) =
+                encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut bytes, true);
+            position += read;
+            match result {
+                EncoderResult::InputEmpty => return Ok(bytes),
+                EncoderResult::OutputFull => continue,
+                EncoderResult::Unmappable(character) => {
+                    let start = position - character.len_utf8();
+                    let line_start = contents[..start].rfind('\n').map_or(0, |v| v + 1);
+                    let line_end = contents[start..]
+                        .find('\n')
+                        .map_or(contents.len(), |v| start + v);
+                    return Err(ProcessError::Unencodable {
+                        charset: *self,
+                        character,
+                        line: contents[..start].matches('\n').count() + 1,
+                        text: contents[line_start..line_end]
+                            .trim_end_matches('\r')
+                            .to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod from_str {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(Charset::from_str("UTF-8").unwrap(), Charset::Utf8);
+            assert_eq!(Charset::from_str("Shift_JIS").unwrap(), Charset::ShiftJis);
+            assert_eq!(Charset::from_str("cp932").unwrap(), Charset::ShiftJis);
+            assert!(Charset::from_str("EUC-JP").is_err());
+        }
+    }
+
+    mod encode {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let result = Charset::ShiftJis.encode("通常顔\n").unwrap();
+            assert_eq!(result, b"\x92\xca\x8f\xed\x8a\xe7\n");
+
+            match Charset::ShiftJis.encode("通常\n照れ😊顔\n") {
+                Err(ProcessError::Unencodable {
+                    charset,
+                    character,
+                    line,
+                    text,
+                }) => {
+                    assert_eq!(charset, Charset::ShiftJis);
+                    assert_eq!(character, '😊');
+                    assert_eq!(line, 2);
+                    assert_eq!(text, "照れ😊顔");
+                }
+                _ => panic!("expected unencodable"),
+            }
+
+            match Charset::ShiftJis.encode("😊通常\r\n照れ\r\n") {
+                Err(ProcessError::Unencodable { line, text, .. }) => {
+                    assert_eq!(line, 1);
+                    assert_eq!(text, "😊通常");
+                }
+                _ => panic!("expected unencodable"),
+            }
+        }
+    }
+
+    mod write_surfacetable_with_charset {
+        use super::*;
+
+        use crate::{
+            options::BuildOptions,
+            process::{parse_yaml, write_surfacetable},
+        };
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let options = BuildOptions::new()
+                .with_charset(Charset::ShiftJis)
+                .with_whitelist(vec![111]);
+
+            let mut buffer = Vec::new();
+            write_surfacetable(&root, &options, &mut buffer).unwrap();
+            let (result, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&buffer);
+            assert!(!had_errors);
+            assert!(result.starts_with("charset,Shift_JIS\nversion,1\n"));
+            assert!(result.contains("111,通常顔-こっち目-前手\n"));
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    ops::RangeInclusive,
+};
+
+use crate::{
+    ast::{Character, Root},
+    error::{ParseError, ProcessError},
+    filter::validate_filters,
+    locate::Segment,
+    options::BuildOptions,
+    process::{generate_character_offsets, number_surfaces},
+    rules::validate_rules,
+    surfaces::build_surfaces,
+};
+
+/// Severity of a problem found by [`check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problem found by [`check`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+}
+
+impl Diagnostic {
+    fn error(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    /// Severity of the problem.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Description of the problem.
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Validates `root` without building anything.
+pub fn check(root: &Root, options: &BuildOptions) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (index, character) in root.characters().iter().enumerate() {
+        check_character(character, index, &mut diagnostics);
+    }
+
+    if let Err(e) = generate_character_offsets(root) {
+        diagnostics.push(Diagnostic::error(e.to_string()));
+        return diagnostics;
+    }
+
+    let mut rules_valid = true;
+    for (index, character) in root.characters().iter().enumerate() {
+        if let Err(e) = validate_rules(character, index) {
+            diagnostics.push(Diagnostic::error(e.to_string()));
+            rules_valid = false;
+        }
+    }
+    if !rules_valid {
+        return diagnostics;
+    }
+
+    if let Err(e) = validate_filters(root, options) {
+        diagnostics.push(Diagnostic::error(e.to_string()));
+    }
+
+    if let Err(e) = build_surfaces(root, options) {
+        if matches!(e.without_file(), ProcessError::Template { .. }) {
+            diagnostics.push(Diagnostic::error(e.to_string()));
+        }
+    }
+
+    let raw_surfaces = root.raw().map(|v| raw_surfaces(v)).unwrap_or_default();
+    if raw_surfaces.is_empty() {
+        return diagnostics;
+    }
+
+    match number_surfaces(root, options.lock()) {
+        Ok(surfaces) => {
+            for (index, character_surfaces) in surfaces.iter().enumerate() {
+                for (number, _) in character_surfaces.iter() {
+                    if raw_surfaces.iter().any(|v| v.contains(number)) {
+                        diagnostics.push(Diagnostic::error(format!(
+                            "surface {} of \\{} is also defined in raw",
+                            number, index
+                        )));
+                    }
+                }
+            }
+        }
+        Err(e) => diagnostics.push(Diagnostic::error(e.to_string())),
+    }
+
+    diagnostics
+}
+
+/// Adds the file and the location of `target` to `message`, if the character is from a file.
+fn locate_message(character: &Character, target: &[Segment], message: String) -> String {
+    match character.origin() {
+        Some(source) => ParseError::in_source(source, target, message).to_string(),
+        None => message,
+    }
+}
+
+fn check_character(character: &Character, index: usize, diagnostics: &mut Vec<Diagnostic>) {
+    if character.parts().is_empty() {
+        diagnostics.push(Diagnostic::warning(format!("\\{} has no parts", index)));
+    }
+
+    let mut groups = HashSet::new();
+    for (index_part, group_data) in character.parts().iter().enumerate() {
+        if !groups.insert(group_data.group()) {
+            let message = format!("\\{} has duplicate group: {}", index, group_data.group());
+            diagnostics.push(Diagnostic::error(locate_message(
+                character,
+                &[
+                    Segment::Key("parts"),
+                    Segment::Index(index_part),
+                    Segment::Key("group"),
+                ],
+                message,
+            )));
+        }
+
+        if group_data.details().is_empty() {
+            diagnostics.push(Diagnostic::error(format!(
+                "group {} of \\{} has no poses, so no surfaces are generated",
+                group_data.group(),
+                index
+            )));
+        }
+
+        let mut poses: HashMap<&String, usize> = HashMap::new();
+        for pose_data in group_data.details().iter() {
+            let occurrence = poses.entry(pose_data.name()).or_default();
+            *occurrence += 1;
+            if *occurrence > 1 {
+                let message = format!(
+                    "group {} of \\{} has duplicate pose: {}",
+                    group_data.group(),
+                    index,
+                    pose_data.name()
+                );
+                diagnostics.push(Diagnostic::error(locate_message(
+                    character,
+                    &[
+                        Segment::Key("parts"),
+                        Segment::Index(index_part),
+                        Segment::Key("details"),
+                        Segment::Named {
+                            key: "name",
+                            value: pose_data.name(),
+                            occurrence: *occurrence,
+                        },
+                    ],
+                    message,
+                )));
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct RawSurfaces {
+    included: Vec<RangeInclusive<usize>>,
+    excluded: Vec<RangeInclusive<usize>>,
+}
+
+impl RawSurfaces {
+    fn contains(&self, number: &usize) -> bool {
+        self.included.iter().any(|v| v.contains(number))
+            && !self.excluded.iter().any(|v| v.contains(number))
+    }
+}
+
+fn raw_surfaces(raw: &str) -> Vec<RawSurfaces> {
+    let mut result = Vec::new();
+
+    for line in raw.lines() {
+        let ids = match line.trim().strip_prefix("surface") {
+            Some(v) if v.starts_with(|c: char| c.is_ascii_digit()) => v,
+            _ => continue,
+        };
+        let ids = ids
+            .split(|c: char| c.is_whitespace() || c == '{')
+            .next()
+            .unwrap_or_default();
+
+        let mut surfaces = RawSurfaces {
+            included: Vec::new(),
+            excluded: Vec::new(),
+        };
+        for token in ids.split(',') {
+            let (token, excluded) = match token.strip_prefix('!') {
+                Some(v) => (v, true),
+                None => (token, false),
+            };
+
+            let range = match token.split_once('-') {
+                Some((start, end)) => start.parse::<usize>().ok().zip(end.parse::<usize>().ok()),
+                None => token.parse::<usize>().ok().map(|v| (v, v)),
+            };
+            if let Some((start, end)) = range {
+                if excluded {
+                    surfaces.excluded.push(start..=end);
+                } else {
+                    surfaces.included.push(start..=end);
+                }
+            }
+        }
+        result.push(surfaces);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod check {
+        use super::*;
+
+        use crate::process::parse_yaml;
+
+        #[test]
+        fn success_when_valid() {
+            let case = include_str!("../test_target/test-001.yaml");
+            let root = parse_yaml(case).unwrap();
+            assert!(check(&root, &BuildOptions::new()).is_empty());
+        }
+
+        #[test]
+        fn checking_value() {
+            let case = r#"
+raw: |
+  surface0,12-20
+  {
+  }
+  surface.append11-22
+  {
+  }
+characters:
+  - parts:
+    - group: face
+      details:
+        - name: normal
+          text: ""
+        - name: normal
+          text: ""
+    - group: face
+      details:
+        - name: front
+          text: ""
+    - group: eyes
+      details: []
+  - parts: []
+"#;
+            let root = parse_yaml(case).unwrap();
+            let result: Vec<String> = check(&root, &BuildOptions::new())
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(
+                result,
+                vec![
+                    "error: group face of \\0 has duplicate pose: normal",
+                    "error: \\0 has duplicate group: face",
+                    "error: group eyes of \\0 has no poses, so no surfaces are generated",
+                    "warning: \\1 has no parts",
+                ]
+            );
+
+            let case = r#"
+raw: |
+  surface0,12-30,!13-20
+  {
+  }
+characters:
+  - parts:
+    - group: face
+      details:
+        - name: normal
+          text: ""
+        - name: shy
+          text: ""
+    - group: eyes
+      details:
+        - name: front
+          text: ""
+        - name: side
+          text: ""
+"#;
+            let root = parse_yaml(case).unwrap();
+            let result: Vec<String> = check(&root, &BuildOptions::new())
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(
+                result,
+                vec![
+                    "error: surface 12 of \\0 is also defined in raw",
+                    "error: surface 21 of \\0 is also defined in raw",
+                    "error: surface 22 of \\0 is also defined in raw",
+                ]
+            );
+        }
+
+        #[test]
+        fn failed_when_overflow() {
+            let poses = (0..10)
+                .map(|v| format!("        - name: pose{}\n          text: \"\"\n", v))
+                .collect::<String>();
+            let groups = (0..20)
+                .map(|v| format!("    - group: group{}\n      details:\n{}", v, poses))
+                .collect::<String>();
+            let case = format!("characters:\n  - parts:\n{}", groups);
+
+            let root = parse_yaml(&case).unwrap();
+            let result: Vec<String> = check(&root, &BuildOptions::new())
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(result, vec!["error: surface numbers of \\0 are too large"]);
+        }
+    }
+
+    mod raw_surfaces {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let case =
+                "descript\n{\n}\nsurface0,5-7,!6 {\n}\nsurface.append10\n{\n}\n  surface100\n";
+            assert_eq!(
+                raw_surfaces(case),
+                vec![
+                    RawSurfaces {
+                        included: vec![0..=0, 5..=7],
+                        excluded: vec![6..=6],
+                    },
+                    RawSurfaces {
+                        included: vec![100..=100],
+                        excluded: vec![],
+                    },
+                ]
+            );
+        }
+    }
+}
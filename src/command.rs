@@ -0,0 +1,708 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use surfacetable_mixer::{
+    diff_surfaces, import_lock, import_surfacetable, label_surfaces, list_surfaces, lookup_names,
+    lookup_number, read_lock, read_surfacetable, update_lock, write_constants, write_lock,
+    write_surfaces, write_surfacetable, BuildOptions, InputFormat, LockEntry, ProcessError, Root,
+    Severity, SurfaceEntry, SurfaceLabel,
+};
+
+use crate::{
+    config::{Config, ListFormat, OnExists},
+    output::{AtomicFile, Output},
+};
+
+/// Path meaning stdin for input, or stdout for output.
+const STDIO_PATH: &str = "-";
+
+const EXIT_FAILURE: i32 = 1;
+const EXIT_EXISTS: i32 = 3;
+const EXIT_NOT_INTERACTIVE: i32 = 4;
+const EXIT_SHIFTED: i32 = 5;
+
+/// Error while running a command.
+#[derive(Debug)]
+pub(crate) enum CommandError {
+    Process(ProcessError),
+    /// Output file exists and `--on-exists=fail` is set.
+    Exists(PathBuf),
+    /// Output file exists, but stdin is not available to ask.
+    NotInteractive(PathBuf),
+    /// User declined to overwrite the file.
+    Cancelled,
+    /// `diff` found existing surface numbers pointing at other combinations.
+    Shifted,
+    /// Failed to watch the input files.
+    Watch(notify::Error),
+}
+
+impl CommandError {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            Self::Process(_) => EXIT_FAILURE,
+            Self::Exists(_) => EXIT_EXISTS,
+            Self::NotInteractive(_) => EXIT_NOT_INTERACTIVE,
+            Self::Cancelled => 0,
+            Self::Shifted => EXIT_SHIFTED,
+            Self::Watch(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl From<ProcessError> for CommandError {
+    fn from(value: ProcessError) -> Self {
+        Self::Process(value)
+    }
+}
+
+impl From<notify::Error> for CommandError {
+    fn from(value: notify::Error) -> Self {
+        Self::Watch(value)
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Process(value.into())
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Process(e) => write!(f, "{}", e),
+            Self::Exists(path) => write!(f, "{} already exists.", path.display()),
+            Self::NotInteractive(path) => write!(
+                f,
+                "{} already exists, and cannot ask to overwrite it without a terminal (use --on-exists).",
+                path.display()
+            ),
+            Self::Cancelled => write!(f, "cancelled."),
+            Self::Shifted => write!(f, "existing surface numbers are shifted."),
+            Self::Watch(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// What to do with an output file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Decision {
+    Write,
+    Skip,
+}
+
+/// What to do with each output file, decided before building.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decisions {
+    output: Decision,
+    surfaces: Decision,
+    constants: Decision,
+    lock: Decision,
+}
+
+pub(crate) fn process(config: &Config) -> Result<(), CommandError> {
+    let decisions = decide_outputs(config)?;
+    generate(config, &decisions)?;
+
+    Ok(())
+}
+
+pub(crate) fn decide_outputs(config: &Config) -> Result<Decisions, CommandError> {
+    let output = decide(config, config.output())?;
+    let surfaces = match config.surfaces() {
+        Some(path) => decide(config, path)?,
+        None => Decision::Skip,
+    };
+    let constants = match config.constants() {
+        Some(path) => decide(config, path)?,
+        None => Decision::Skip,
+    };
+
+    // The lock only records numbers written to some output.
+    let written = [output, surfaces, constants].contains(&Decision::Write);
+    let lock = match config.lock() {
+        Some(path) if written => decide(config, path)?,
+        _ => Decision::Skip,
+    };
+
+    Ok(Decisions {
+        output,
+        surfaces,
+        constants,
+        lock,
+    })
+}
+
+/// Builds and writes the outputs, returning the surfaces in them.
+pub(crate) fn generate(
+    config: &Config,
+    decisions: &Decisions,
+) -> Result<Vec<SurfaceEntry>, CommandError> {
+    let root = read_root(config)?;
+    let mut options = config.build_options()?;
+
+    if let Some(path) = config.lock() {
+        let previous = if path.exists() && !config.regenerate_lock() {
+            Some(read_lock(path)?)
+        } else {
+            None
+        };
+        let update = update_lock(&root, previous.as_ref())?;
+
+        if previous.is_some() {
+            for entry in update.added() {
+                eprintln!("added: {}", describe_entry(entry, config.separator()));
+            }
+            for entry in update.retired() {
+                eprintln!("retired: {}", describe_entry(entry, config.separator()));
+            }
+        }
+
+        options = options.with_lock(update.lock().clone());
+    }
+
+    if decisions.output == Decision::Write {
+        let mut writer = create_output(config, config.output())?;
+        write_surfacetable(&root, &options, &mut writer)?;
+        writer.commit()?;
+
+        report_saved(config.output());
+    }
+
+    if let Some(path) = config.surfaces() {
+        if decisions.surfaces == Decision::Write {
+            let mut writer = create_output(config, path)?;
+            write_surfaces(&root, &options, &mut writer)?;
+            writer.commit()?;
+
+            report_saved(path);
+        }
+    }
+
+    if let Some(path) = config.constants() {
+        if decisions.constants == Decision::Write {
+            let mut writer = create_output(config, path)?;
+            write_constants(&root, &options, &mut writer)?;
+            writer.commit()?;
+
+            report_saved(path);
+        }
+    }
+
+    if let (Some(path), Some(lock), Decision::Write) =
+        (config.lock(), options.lock(), decisions.lock)
+    {
+        let mut writer = create_output(config, path)?;
+        write_lock(lock, &mut writer)?;
+        writer.commit()?;
+
+        report_saved(path);
+    }
+
+    Ok(list_surfaces(&root, &options)?)
+}
+
+/// Returns `false` if some errors are found.
+pub(crate) fn check(config: &Config) -> Result<bool, CommandError> {
+    let root = read_root(config)?;
+    let options = read_options(config)?;
+
+    let diagnostics = surfacetable_mixer::check(&root, &options);
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|v| v.severity() == Severity::Error)
+        .count();
+    if diagnostics.is_empty() {
+        println!("no problems found in {}.", config.input().display());
+    } else {
+        println!(
+            "{} error(s), {} warning(s) found in {}.",
+            errors,
+            diagnostics.len() - errors,
+            config.input().display()
+        );
+    }
+
+    Ok(errors == 0)
+}
+
+/// Returns `false` if no surfaces are found.
+pub(crate) fn lookup(
+    config: &Config,
+    number: Option<usize>,
+    names: &[String],
+) -> Result<bool, CommandError> {
+    let root = read_root(config)?;
+    let options = read_options(config)?;
+
+    if let Some(number) = number {
+        let detail = match lookup_number(&root, &options, number)? {
+            Some(v) => v,
+            None => {
+                eprintln!("surface {} is not generated.", number);
+                return Ok(false);
+            }
+        };
+
+        println!("\\{} {}", detail.character(), detail.number());
+        for pose in detail.poses() {
+            println!("  {}: {}", pose.group(), pose.name());
+        }
+        if let Some(base) = detail.base() {
+            println!("\n// base\n{}", base.trim_end_matches('\n'));
+        }
+        for pose in detail.poses() {
+            println!(
+                "\n// {}\n{}",
+                pose.name(),
+                pose.text().trim_end_matches('\n')
+            );
+        }
+        return Ok(true);
+    }
+
+    let entries = lookup_names(&root, &options, names)?;
+    if entries.is_empty() {
+        eprintln!(
+            "surface of {} is not generated.",
+            names.join(config.separator())
+        );
+        return Ok(false);
+    }
+    for entry in entries.iter() {
+        println!("\\{} {}", entry.character(), entry.number());
+    }
+
+    Ok(true)
+}
+
+pub(crate) fn list(config: &Config, format: ListFormat) -> Result<(), CommandError> {
+    let root = read_root(config)?;
+    let options = read_options(config)?;
+    let entries = list_surfaces(&root, &options)?;
+
+    let contents = match format {
+        ListFormat::Table => format_table(&entries, config.separator()),
+        ListFormat::Csv => format_csv(&entries, config.separator()),
+        ListFormat::Json => serde_json::to_string_pretty(&entries).map_err(ProcessError::from)?,
+    };
+    writeln!(
+        std::io::stdout().lock(),
+        "{}",
+        contents.trim_end_matches('\n')
+    )?;
+
+    Ok(())
+}
+
+/// Fails with [`CommandError::Shifted`] if some existing surface numbers point at other
+/// combinations.
+pub(crate) fn diff(config: &Config, old: &Path, new: Option<&Path>) -> Result<(), CommandError> {
+    let options = read_options(config)?;
+    let old_surfaces = read_labels(config, old, InputFormat::from_path(old), &options)?;
+    let new_surfaces = match new {
+        Some(path) => read_labels(config, path, InputFormat::from_path(path), &options)?,
+        None => read_labels(config, config.input(), config.input_format(), &options)?,
+    };
+
+    let diff = diff_surfaces(&old_surfaces, &new_surfaces);
+    let describe = |v: &SurfaceLabel| format!("\\{} {},{}", v.character(), v.number(), v.label());
+    for surface in diff.added() {
+        println!("added: {}", describe(surface));
+    }
+    for surface in diff.removed() {
+        println!("removed: {}", describe(surface));
+    }
+    for (old, new) in diff.changed() {
+        println!("changed: {} -> {}", describe(old), new.label());
+    }
+    for (old, new) in diff.renumbered() {
+        println!("renumbered: {} -> {}", describe(old), new.number());
+    }
+
+    if diff == Default::default() {
+        println!("no differences found.");
+    } else {
+        println!(
+            "{} added, {} removed, {} changed, {} renumbered.",
+            diff.added().len(),
+            diff.removed().len(),
+            diff.changed().len(),
+            diff.renumbered().len()
+        );
+    }
+
+    if diff.is_shifted() {
+        return Err(CommandError::Shifted);
+    }
+
+    Ok(())
+}
+
+/// Writes the skeleton built from `surfacetable` to the input file.
+pub(crate) fn import(config: &Config, surfacetable: &Path) -> Result<(), CommandError> {
+    let path = config.input();
+    if decide(config, path)? == Decision::Skip {
+        return Ok(());
+    }
+
+    let surfaces = read_surfacetable(surfacetable)?;
+    let root = import_surfacetable(&surfaces, config.separator());
+    let contents = config.input_format().serialize(&root)?;
+
+    let mut writer = create_output(config, path)?;
+    writer.write_all(contents.as_bytes())?;
+    writer.commit()?;
+
+    report_saved(path);
+
+    match config.lock() {
+        Some(lock_path) => {
+            if decide(config, lock_path)? == Decision::Write {
+                let mut writer = create_output(config, lock_path)?;
+                write_lock(&import_lock(&surfaces, config.separator()), &mut writer)?;
+                writer.commit()?;
+
+                report_saved(lock_path);
+            }
+        }
+        None => {
+            let options = BuildOptions::new().with_separator(config.separator());
+            let diff = diff_surfaces(&surfaces, &label_surfaces(&root, &options)?);
+            if diff.is_shifted() {
+                eprintln!(
+                    "{} surfaces get other numbers when built; pass --lock to keep them.",
+                    diff.changed().len() + diff.renumbered().len()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads surfacetable.txt as is, or builds the surfaces of the other inputs.
+fn read_labels(
+    config: &Config,
+    path: &Path,
+    format: InputFormat,
+    options: &BuildOptions,
+) -> Result<Vec<SurfaceLabel>, ProcessError> {
+    if path
+        .extension()
+        .is_some_and(|v| v.eq_ignore_ascii_case("txt"))
+    {
+        return read_surfacetable(path);
+    }
+
+    let root = read_input(path, format)?;
+    let root = match config.offset() {
+        Some(offset) => root.with_offset(offset),
+        None => root,
+    };
+    label_surfaces(&root, options)
+}
+
+fn format_table(entries: &[SurfaceEntry], separator: &str) -> String {
+    let number_width = entries
+        .iter()
+        .map(|v| v.number().to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max("number".len());
+
+    let mut lines = vec![format!(
+        "character  {:>width$}  name  poses",
+        "number",
+        width = number_width
+    )];
+    for entry in entries.iter() {
+        let poses: Vec<String> = entry
+            .poses()
+            .iter()
+            .map(|v| {
+                format!(
+                    "{}={}[{}]",
+                    v.group(),
+                    v.name(),
+                    describe_digits(v.digits())
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{:<9}  {:>width$}  {}  {}",
+            format!("\\{}", entry.character()),
+            entry.number(),
+            entry.names().join(separator),
+            poses.join(" "),
+            width = number_width
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn format_csv(entries: &[SurfaceEntry], separator: &str) -> String {
+    let groups = entries
+        .iter()
+        .map(|v| v.poses().len())
+        .max()
+        .unwrap_or_default();
+
+    let mut header = vec![
+        "character".to_string(),
+        "number".to_string(),
+        "name".to_string(),
+    ];
+    for index in 1..=groups {
+        header.push(format!("group{}", index));
+        header.push(format!("pose{}", index));
+        header.push(format!("digits{}", index));
+    }
+
+    let mut lines = vec![header.join(",")];
+    for entry in entries.iter() {
+        let mut fields = vec![
+            entry.character().to_string(),
+            entry.number().to_string(),
+            escape_csv(&entry.names().join(separator)),
+        ];
+        for pose in entry.poses() {
+            fields.push(escape_csv(pose.group()));
+            fields.push(escape_csv(pose.name()));
+            fields.push(describe_digits(pose.digits()));
+        }
+        lines.push(fields.join(","));
+    }
+
+    lines.join("\n")
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn describe_digits((lowest, highest): (usize, usize)) -> String {
+    if lowest == highest {
+        lowest.to_string()
+    } else {
+        format!("{}-{}", lowest, highest)
+    }
+}
+
+/// Options with the lock file, which is only read.
+fn read_options(config: &Config) -> Result<BuildOptions, ProcessError> {
+    let mut options = config.build_options()?;
+
+    if let Some(path) = config.lock() {
+        if path.exists() {
+            options = options.with_lock(read_lock(path)?);
+        }
+    }
+
+    Ok(options)
+}
+
+pub(crate) fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+fn exists(path: &Path) -> bool {
+    !is_stdio(path) && path.exists()
+}
+
+fn decide(config: &Config, path: &Path) -> Result<Decision, CommandError> {
+    if !exists(path) {
+        return Ok(Decision::Write);
+    }
+
+    match config.on_exists() {
+        OnExists::Overwrite | OnExists::Backup => Ok(Decision::Write),
+        OnExists::Skip => {
+            eprintln!("skipped {} (already exists).", path.display());
+            Ok(Decision::Skip)
+        }
+        OnExists::Fail => Err(CommandError::Exists(path.to_path_buf())),
+        OnExists::Ask => {
+            if is_stdio(config.input()) || !std::io::stdin().is_terminal() {
+                return Err(CommandError::NotInteractive(path.to_path_buf()));
+            }
+            confirm_overwriting(path)?;
+            Ok(Decision::Write)
+        }
+    }
+}
+
+fn read_root(config: &Config) -> Result<Root, ProcessError> {
+    let root = read_input(config.input(), config.input_format())?;
+
+    Ok(match config.offset() {
+        Some(offset) => root.with_offset(offset),
+        None => root,
+    })
+}
+
+fn read_input(path: &Path, format: InputFormat) -> Result<Root, ProcessError> {
+    if !is_stdio(path) {
+        return format.read(path);
+    }
+
+    let mut buffer = String::new();
+    std::io::stdin().read_to_string(&mut buffer)?;
+    format.parse(&buffer)
+}
+
+fn create_output<'a>(config: &Config, path: &'a Path) -> Result<Output<'a>, ProcessError> {
+    if is_stdio(path) {
+        return Ok(Output::Stdout(std::io::stdout().lock()));
+    }
+
+    Ok(Output::File(AtomicFile::create(path, config.backup())?))
+}
+
+fn report_saved(path: &Path) {
+    if !is_stdio(path) {
+        eprintln!("saved to {}.", path.display());
+    }
+}
+
+fn describe_entry(entry: &LockEntry, separator: &str) -> String {
+    format!(
+        "\\{} {},{}",
+        entry.character(),
+        entry.number(),
+        entry.names().join(separator)
+    )
+}
+
+fn confirm_overwriting(path: &Path) -> Result<(), CommandError> {
+    let stdin = std::io::stdin();
+    let mut buf_reader = std::io::BufReader::new(stdin);
+
+    let stderr = std::io::stderr();
+    let stderr_lock = stderr.lock();
+    let mut buf_writer = std::io::BufWriter::new(stderr_lock);
+
+    let mut s = String::new();
+    buf_writer.write_all(format!("{} already exists.\n", path.display()).as_bytes())?;
+
+    loop {
+        buf_writer.write_all(b"Do you want to overwrite the file? [Y/n]: ")?;
+        buf_writer.flush()?;
+
+        s.clear();
+        buf_reader.read_line(&mut s)?;
+
+        match s.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => {
+                buf_writer.write_all(b"The file will be overwritten.\n")?;
+                buf_writer.flush()?;
+                break;
+            }
+            "n" | "no" => {
+                buf_writer.write_all(b"Closing this program...\n")?;
+                buf_writer.flush()?;
+                return Err(CommandError::Cancelled);
+            }
+            _ => {
+                buf_writer.write_all(
+                    b"Please input 'Y' or 'n' (To exit from this program, input 'n').\n",
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use surfacetable_mixer::parse_yaml;
+
+    const INPUT: &str = r#"characters:
+  - parts:
+      - group: 顔
+        details:
+          - name: 通常, 笑顔
+            text: a
+          - name: "\"照れ\""
+            text: b
+          - name: "泣き\n顔"
+            text: c
+"#;
+
+    fn entries() -> Vec<SurfaceEntry> {
+        let root = parse_yaml(INPUT).unwrap();
+        list_surfaces(&root, &BuildOptions::new()).unwrap()
+    }
+
+    mod format_table {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(
+                format_table(&entries(), "-"),
+                concat!(
+                    "character  number  name  poses\n",
+                    "\\0              1  通常, 笑顔  顔=通常, 笑顔[1]\n",
+                    "\\0              2  \"照れ\"  顔=\"照れ\"[1]\n",
+                    "\\0              3  泣き\n顔  顔=泣き\n顔[1]",
+                )
+            );
+        }
+    }
+
+    mod format_csv {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(
+                format_csv(&entries(), "-"),
+                concat!(
+                    "character,number,name,group1,pose1,digits1\n",
+                    "0,1,\"通常, 笑顔\",顔,\"通常, 笑顔\",1\n",
+                    "0,2,\"\"\"照れ\"\"\",顔,\"\"\"照れ\"\"\",1\n",
+                    "0,3,\"泣き\n顔\",顔,\"泣き\n顔\",1",
+                )
+            );
+        }
+    }
+
+    mod escape_csv {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(escape_csv("通常顔"), "通常顔");
+            assert_eq!(escape_csv("a,b"), "\"a,b\"");
+            assert_eq!(escape_csv("a\"b"), "\"a\"\"b\"");
+            assert_eq!(escape_csv("a\r\nb"), "\"a\r\nb\"");
+        }
+    }
+
+    mod describe_digits {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(describe_digits((2, 2)), "2");
+            assert_eq!(describe_digits((1, 3)), "1-3");
+        }
+    }
+}
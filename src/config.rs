@@ -1,90 +1,390 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 
-use crate::process::process;
+use surfacetable_mixer::{
+    read_whitelist, BuildOptions, Charset, ConstantFormat, ConstantTemplate, InputFormat,
+    LineEnding, NameFilter, ProcessError, Whitelist,
+};
+
+use crate::{
+    command::{check, diff, import, is_stdio, list, lookup, process, CommandError},
+    output::{Backup, BackupMode},
+    settings::{Settings, SettingsError},
+    watch::watch,
+};
 
 const DEFAULT_TARGET_PATH: &str = "./surfaces.yaml";
 const DEFAULT_OUTPUT_PATH: &str = "./surfacetable.txt";
 const DEFAULT_SEPARATOR: &str = "-";
+const DEFAULT_BACKUP_KEEP: usize = 5;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub(crate) struct Config {
-    /// Path to input file.
-    #[arg(short, long, default_value = DEFAULT_TARGET_PATH)]
-    input: PathBuf,
-    /// Path to output file.
-    #[arg(short, long, default_value = DEFAULT_OUTPUT_PATH)]
-    output: PathBuf,
-    /// Flag of force overwriting.
-    #[arg(short, long, default_value_t = false)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to project config file [default: ./surfacetable-mixer.toml if exists].
+    #[arg(long, default_value = None, global = true)]
+    config: Option<PathBuf>,
+    /// Path to input file (`-` for stdin) [default: ./surfaces.yaml].
+    #[arg(short, long, default_value = None, global = true)]
+    input: Option<PathBuf>,
+    /// Format of the input file (yaml, json or toml) [default: by extension, yaml for stdin].
+    #[arg(long, default_value = None, value_parser = InputFormat::from_str, global = true)]
+    input_format: Option<InputFormat>,
+    /// Path to output file (`-` for stdout) [default: ./surfacetable.txt].
+    #[arg(short, long, default_value = None, global = true)]
+    output: Option<PathBuf>,
+    /// Path to output file of surfaces.txt (`-` for stdout, not generated if omitted).
+    #[arg(long, default_value = None, global = true)]
+    surfaces: Option<PathBuf>,
+    /// Path to output file of surface number constants (`-` for stdout, not generated if omitted).
+    #[arg(long, default_value = None, global = true)]
+    constants: Option<PathBuf>,
+    /// Format of the constants file (key-value, yaya, satori or kawari) [default: key-value].
+    #[arg(long, default_value = None, value_parser = ConstantFormat::from_str, global = true)]
+    constants_format: Option<ConstantFormat>,
+    /// Template of constant names, such as `s{character}_{pose.目}_{pose.口}` [default: {names}].
+    #[arg(long, default_value = None, value_parser = ConstantTemplate::from_str, global = true)]
+    constants_template: Option<ConstantTemplate>,
+    /// Flag of force overwriting (same as `--on-exists=overwrite`).
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with = "on_exists",
+        global = true
+    )]
     force: bool,
-    /// Whitelist for surfaces, separated by comma.
-    #[arg(short, long, default_value = None, value_parser = whitelist_in_csv)]
-    whitelist: Option<std::vec::Vec<usize>>,
-    /// Separator string for a parts of the surface.
-    #[arg(short, long, default_value = DEFAULT_SEPARATOR)]
-    separator: String,
+    /// What to do when an output file already exists [default: ask].
+    #[arg(long, value_enum, default_value = None, global = true)]
+    on_exists: Option<OnExists>,
+    /// Keep the previous version of output files when replacing them.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "simple", global = true)]
+    backup: Option<BackupMode>,
+    /// Number of timestamped backups to keep for each file.
+    #[arg(long, default_value_t = DEFAULT_BACKUP_KEEP, global = true)]
+    backup_keep: usize,
+    /// Whitelist for surfaces, such as `1100-1199,\1:1011-1022,!1150`.
+    #[arg(short, long, default_value = None, value_parser = Whitelist::from_str, global = true)]
+    whitelist: Option<Whitelist>,
+    /// Path to whitelist file, in text or YAML list (combined with `--whitelist`).
+    #[arg(long, default_value = None, global = true)]
+    whitelist_file: Option<PathBuf>,
+    /// Filter of surfaces by pose names, such as `目=こっち目,顔色=*` (can be repeated).
+    #[arg(long, value_parser = NameFilter::from_str, global = true)]
+    name_whitelist: Vec<NameFilter>,
+    /// Filter of surfaces not to output, in the same format as `--name-whitelist` (can be repeated).
+    #[arg(long, value_parser = NameFilter::from_str, global = true)]
+    name_blacklist: Vec<NameFilter>,
+    /// Separator string for a parts of the surface [default: -].
+    #[arg(short, long, default_value = None, global = true)]
+    separator: Option<String>,
+    /// Character encoding of the output files (UTF-8 or Shift_JIS) [default: UTF-8].
+    #[arg(long, default_value = None, value_parser = Charset::from_str, global = true)]
+    charset: Option<Charset>,
+    /// Line ending of the output files (lf, crlf or native) [default: lf].
+    #[arg(long, default_value = None, value_parser = LineEnding::from_str, global = true)]
+    line_ending: Option<LineEnding>,
+    /// Flag of writing a BOM at the beginning of UTF-8 output files (`--bom=false` to disable).
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        global = true
+    )]
+    bom: Option<bool>,
+    /// Interval of surface numbers between characters, overriding `offset` in the input.
+    #[arg(long, default_value = None, global = true)]
+    offset: Option<usize>,
+    /// Path to lock file keeping surface numbers (not used if omitted).
+    #[arg(short, long, default_value = None, global = true)]
+    lock: Option<PathBuf>,
+    /// Flag of discarding the lock file and assigning surface numbers again.
+    #[arg(long, default_value_t = false, global = true)]
+    regenerate_lock: bool,
 }
 
-fn whitelist_in_csv(s: &str) -> Result<Vec<usize>, String> {
-    let mut whitelist = Vec::new();
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OnExists {
+    /// Ask whether to overwrite (fails if stdin is not a terminal).
+    Ask,
+    /// Overwrite the file.
+    Overwrite,
+    /// Keep the file and do not write it.
+    Skip,
+    /// Stop with an error.
+    Fail,
+    /// Overwrite the file, keeping the previous one as with `--backup`.
+    Backup,
+}
 
-    for (index, element) in s.split(',').enumerate() {
-        match element.parse::<usize>() {
-            Ok(v) => whitelist.push(v),
-            Err(e) => {
-                return Err(format!("Whitelist is invalid: element: {}: {}", index, e));
-            }
-        }
-    }
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Validate the input file without writing anything.
+    Check,
+    /// Rebuild the output files whenever the input file changes.
+    Watch,
+    /// Show the poses of a surface number, or the number of poses.
+    Lookup {
+        /// Surface number to show the poses and texts of.
+        #[arg(required_unless_present = "names", conflicts_with = "names")]
+        number: Option<usize>,
+        /// Pose names in order of the groups, separated by comma.
+        #[arg(long, value_delimiter = ',')]
+        names: Vec<String>,
+    },
+    /// List the surfaces to output with their poses.
+    List {
+        /// Format of the list.
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+    /// Compare the surfaces of two inputs, or an input and a generated surfacetable.txt.
+    Diff {
+        /// Old input file, or surfacetable.txt.
+        old: PathBuf,
+        /// New input file, or surfacetable.txt [default: the input file].
+        new: Option<PathBuf>,
+    },
+    /// Write a skeleton of the input file from an existing surfacetable.txt.
+    Import {
+        /// Path to surfacetable.txt to read.
+        surfacetable: PathBuf,
+    },
+}
 
-    Ok(whitelist)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ListFormat {
+    /// Aligned text for reading.
+    Table,
+    /// Comma-separated values with a header.
+    Csv,
+    /// JSON array.
+    Json,
 }
 
 impl Config {
-    #[cfg(test)]
-    pub fn new(
-        input: PathBuf,
-        output: PathBuf,
-        force: bool,
-        whitelist: Option<Vec<usize>>,
-        separator: String,
-    ) -> Config {
-        Config {
-            input,
-            output,
-            force,
-            whitelist,
-            separator,
+    /// Fills the options not given in the command line with the project config file.
+    pub(crate) fn apply_settings(&mut self) -> Result<(), SettingsError> {
+        let settings = Settings::load(self.config.as_deref())?;
+
+        self.input = self.input.take().or(settings.input);
+        self.input_format = self.input_format.or(settings.input_format);
+        self.output = self.output.take().or(settings.output);
+        self.surfaces = self.surfaces.take().or(settings.surfaces);
+        self.constants = self.constants.take().or(settings.constants);
+        self.constants_format = self.constants_format.or(settings.constants_format);
+        self.constants_template = self
+            .constants_template
+            .take()
+            .or(settings.constants_template);
+        self.lock = self.lock.take().or(settings.lock);
+        self.separator = self.separator.take().or(settings.separator);
+        self.whitelist = self.whitelist.take().or(settings.whitelist);
+        self.whitelist_file = self.whitelist_file.take().or(settings.whitelist_file);
+        if self.name_whitelist.is_empty() {
+            self.name_whitelist = settings.name_whitelist;
         }
+        if self.name_blacklist.is_empty() {
+            self.name_blacklist = settings.name_blacklist;
+        }
+        self.charset = self.charset.or(settings.charset);
+        self.line_ending = self.line_ending.or(settings.line_ending);
+        self.bom = self.bom.or(settings.bom);
+        self.offset = self.offset.or(settings.offset);
+        if !self.force {
+            self.on_exists = self.on_exists.or(settings.on_exists);
+        }
+
+        Ok(())
     }
 
-    pub fn input(&self) -> &PathBuf {
-        &self.input
+    /// Checks the combination of the arguments and the settings.
+    fn validate(&self) -> Result<(), clap::Error> {
+        let stdout: Vec<&str> = [
+            ("--output", &self.output),
+            ("--surfaces", &self.surfaces),
+            ("--constants", &self.constants),
+        ]
+        .into_iter()
+        .filter(|(_, path)| path.as_deref().is_some_and(is_stdio))
+        .map(|(name, _)| name)
+        .collect();
+        if stdout.len() > 1 {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                format!("only one of {} can write to stdout", stdout.join(", ")),
+            ));
+        }
+        if self.regenerate_lock && self.lock.is_none() {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--regenerate-lock requires --lock or lock in the config file",
+            ));
+        }
+
+        Ok(())
     }
 
-    pub fn output(&self) -> &PathBuf {
-        &self.output
+    pub fn input(&self) -> &Path {
+        self.input
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_TARGET_PATH))
     }
 
-    pub fn force(&self) -> &bool {
-        &self.force
+    pub fn input_format(&self) -> InputFormat {
+        self.input_format
+            .unwrap_or_else(|| InputFormat::from_path(self.input()))
     }
 
-    pub fn whitelist(&self) -> Option<&Vec<usize>> {
+    pub fn output(&self) -> &Path {
+        self.output
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_OUTPUT_PATH))
+    }
+
+    pub fn surfaces(&self) -> Option<&PathBuf> {
+        self.surfaces.as_ref()
+    }
+
+    pub fn constants(&self) -> Option<&PathBuf> {
+        self.constants.as_ref()
+    }
+
+    pub fn on_exists(&self) -> OnExists {
+        if self.force {
+            OnExists::Overwrite
+        } else {
+            self.on_exists.unwrap_or(OnExists::Ask)
+        }
+    }
+
+    pub fn backup(&self) -> Option<Backup> {
+        let mode = match (self.backup, self.on_exists()) {
+            (Some(v), _) => v,
+            (None, OnExists::Backup) => BackupMode::Simple,
+            (None, _) => return None,
+        };
+        Some(Backup {
+            mode,
+            keep: self.backup_keep,
+        })
+    }
+
+    pub fn whitelist(&self) -> Option<&Whitelist> {
         self.whitelist.as_ref()
     }
 
-    pub fn separator(&self) -> &String {
-        &self.separator
+    pub fn whitelist_file(&self) -> Option<&PathBuf> {
+        self.whitelist_file.as_ref()
     }
 
-    pub fn run(&self) {
-        if let Err(err) = process(self) {
+    pub fn separator(&self) -> &str {
+        self.separator.as_deref().unwrap_or(DEFAULT_SEPARATOR)
+    }
+
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    pub fn lock(&self) -> Option<&PathBuf> {
+        self.lock.as_ref()
+    }
+
+    pub fn regenerate_lock(&self) -> &bool {
+        &self.regenerate_lock
+    }
+
+    pub fn build_options(&self) -> Result<BuildOptions, ProcessError> {
+        let mut options = BuildOptions::new()
+            .with_separator(self.separator())
+            .with_charset(self.charset.unwrap_or_default())
+            .with_line_ending(self.line_ending.unwrap_or_default())
+            .with_bom(self.bom.unwrap_or_default())
+            .with_constant_format(self.constants_format.unwrap_or_default())
+            .with_constant_template(self.constants_template.clone().unwrap_or_default())
+            .with_name_whitelist(self.name_whitelist.clone())
+            .with_name_blacklist(self.name_blacklist.clone());
+
+        let mut whitelist = self.whitelist().cloned();
+        if let Some(path) = self.whitelist_file() {
+            let entries = read_whitelist(path)?;
+            match whitelist.as_mut() {
+                Some(v) => v.extend(entries),
+                None => whitelist = Some(entries),
+            }
+        }
+        if let Some(whitelist) = whitelist {
+            options = options.with_whitelist(whitelist);
+        }
+
+        Ok(options)
+    }
+
+    pub fn run(mut self) {
+        if let Err(err) = self.apply_settings() {
             eprintln!("Application error: {}", err);
             std::process::exit(1);
         }
+        if let Err(err) = self.validate() {
+            err.exit();
+        }
+
+        let result = match self.command {
+            Some(Command::Check) => check(&self),
+            Some(Command::Watch) => watch(&self).map(|_| true),
+            Some(Command::Lookup { number, ref names }) => lookup(&self, number, names),
+            Some(Command::List { format }) => list(&self, format).map(|_| true),
+            Some(Command::Diff { ref old, ref new }) => {
+                diff(&self, old, new.as_deref()).map(|_| true)
+            }
+            Some(Command::Import { ref surfacetable }) => import(&self, surfacetable).map(|_| true),
+            None => process(&self).map(|_| true),
+        };
+
+        match result {
+            Ok(true) | Err(CommandError::Cancelled) => {}
+            Ok(false) => std::process::exit(1),
+            Err(err @ CommandError::Shifted) => std::process::exit(err.exit_code()),
+            Err(err) => {
+                eprintln!("Application error: {}", err);
+                std::process::exit(err.exit_code());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod validate {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let config = Config::parse_from(["surfacetable-mixer", "-o", "-"]);
+            assert!(config.validate().is_ok());
+
+            let config = Config::parse_from(["surfacetable-mixer", "-o", "-", "--surfaces", "-"]);
+            assert_eq!(
+                config.validate().unwrap_err().kind(),
+                ErrorKind::ArgumentConflict
+            );
+
+            let config = Config::parse_from(["surfacetable-mixer", "--regenerate-lock"]);
+            assert_eq!(
+                config.validate().unwrap_err().kind(),
+                ErrorKind::MissingRequiredArgument
+            );
+        }
     }
 }
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use crate::{
+    ast::Root,
+    error::ProcessError,
+    list::{list_surfaces, SurfaceEntry},
+    options::BuildOptions,
+};
+
+const DEFAULT_TEMPLATE: &str = "{names}";
+const GROUP_PREFIX: &str = "pose.";
+
+/// Format of the constants file for SHIORI dictionaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConstantFormat {
+    /// `name=number`
+    #[default]
+    KeyValue,
+    /// `#define name number`
+    Yaya,
+    /// `＠name` followed by the number.
+    Satori,
+    /// `name : number`
+    Kawari,
+}
+
+impl FromStr for ConstantFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "key-value" | "kv" => Ok(ConstantFormat::KeyValue),
+            "yaya" => Ok(ConstantFormat::Yaya),
+            "satori" => Ok(ConstantFormat::Satori),
+            "kawari" => Ok(ConstantFormat::Kawari),
+            _ => Err(format!(
+                "Constant format is invalid: {}: expected key-value, yaya, satori or kawari",
+                s
+            )),
+        }
+    }
+}
+
+impl ConstantFormat {
+    fn format(&self, name: &str, number: usize) -> String {
+        match self {
+            ConstantFormat::KeyValue => format!("{}={}", name, number),
+            ConstantFormat::Yaya => format!("#define {} {}", name, number),
+            ConstantFormat::Satori => format!("＠{}\n{}", name, number),
+            ConstantFormat::Kawari => format!("{} : {}", name, number),
+        }
+    }
+}
+
+/// Template of constant names, such as `{character}_{pose.目}_{pose.口}`.
+///
+/// `{names}` is the pose names joined by the separator, `{character}` is the index of
+/// the character, `{number}` is the surface number, and `{pose.GROUP}` is the pose name
+/// of the group. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstantTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Names,
+    Character,
+    Number,
+    Group(String),
+}
+
+impl Default for ConstantTemplate {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE.parse().unwrap()
+    }
+}
+
+impl FromStr for ConstantTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| format!("Constant name template is invalid: {}: {}", s, reason);
+
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find(['{', '}']) {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            if rest.starts_with('}') {
+                return Err(error("unmatched '}'"));
+            }
+
+            let end = rest.find('}').ok_or_else(|| error("unmatched '{'"))?;
+            let segment = match rest[1..end].trim() {
+                "" => return Err(error("empty placeholder")),
+                "names" => Segment::Names,
+                "character" => Segment::Character,
+                "number" => Segment::Number,
+                v => match v.strip_prefix(GROUP_PREFIX).map(str::trim) {
+                    Some(group) if !group.is_empty() => Segment::Group(group.to_string()),
+                    _ => {
+                        return Err(error(&format!(
+                            "unknown placeholder {{{}}} (use {{{}{}}} for a group)",
+                            v, GROUP_PREFIX, v
+                        )))
+                    }
+                },
+            };
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(segment);
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(ConstantTemplate { segments })
+    }
+}
+
+impl ConstantTemplate {
+    /// Name of the constant for `entry`.
+    pub fn render(&self, entry: &SurfaceEntry, separator: &str) -> Result<String, ProcessError> {
+        let mut name = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Text(v) => name.push_str(v),
+                Segment::Names => name.push_str(&entry.names().join(separator)),
+                Segment::Character => name.push_str(&entry.character().to_string()),
+                Segment::Number => name.push_str(&entry.number().to_string()),
+                Segment::Group(group) => {
+                    let pose = entry
+                        .poses()
+                        .iter()
+                        .find(|v| v.group() == group)
+                        .ok_or_else(|| ProcessError::UnknownGroup {
+                            character: entry.character(),
+                            group: group.clone(),
+                        })?;
+                    name.push_str(pose.name());
+                }
+            }
+        }
+        Ok(name)
+    }
+}
+
+/// Writes the constants of surface numbers built from `root` to `writer`.
+pub fn write_constants<W: Write>(
+    root: &Root,
+    options: &BuildOptions,
+    writer: &mut W,
+) -> Result<(), ProcessError> {
+    let contents = build_constants(root, options)?;
+    writer.write_all(&options.encode(&contents)?)?;
+    Ok(())
+}
+
+/// Builds the constants of surface numbers from `root`.
+///
+/// Surfaces are the same as the ones in [`crate::build_surfacetable`].
+pub fn build_constants(root: &Root, options: &BuildOptions) -> Result<String, ProcessError> {
+    let format = options.constant_format();
+    let template = options.constant_template();
+
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+    let mut lines = Vec::new();
+    for entry in list_surfaces(root, options)? {
+        let name = template.render(&entry, options.separator())?;
+        if let Some(number) = numbers.insert(name.clone(), entry.number()) {
+            return Err(ProcessError::DuplicateConstant {
+                name,
+                numbers: (number, entry.number()),
+            });
+        }
+        lines.push(format.format(&name, entry.number()));
+    }
+
+    let separator = match format {
+        ConstantFormat::Satori => "\n\n",
+        _ => "\n",
+    };
+    Ok(lines.join(separator) + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::parse_yaml;
+
+    mod from_str {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(
+                ConstantTemplate::from_str("s{character}_{pose.目}")
+                    .unwrap()
+                    .segments,
+                vec![
+                    Segment::Text("s".to_string()),
+                    Segment::Character,
+                    Segment::Text("_".to_string()),
+                    Segment::Group("目".to_string()),
+                ]
+            );
+            assert_eq!(
+                ConstantTemplate::from_str("{{{pose.names}}}_{names}")
+                    .unwrap()
+                    .segments,
+                vec![
+                    Segment::Text("{".to_string()),
+                    Segment::Group("names".to_string()),
+                    Segment::Text("}_".to_string()),
+                    Segment::Names,
+                ]
+            );
+            assert_eq!(
+                ConstantTemplate::from_str("{目}"),
+                Err(
+                    "Constant name template is invalid: {目}: unknown placeholder {目} (use {pose.目} for a group)"
+                        .to_string()
+                )
+            );
+            assert!(ConstantTemplate::from_str("{pose.}").is_err());
+            assert!(ConstantTemplate::from_str("{names").is_err());
+            assert!(ConstantTemplate::from_str("names}").is_err());
+            assert!(ConstantTemplate::from_str("{}").is_err());
+            assert_eq!(
+                ConstantFormat::from_str("Key_Value"),
+                Ok(ConstantFormat::KeyValue)
+            );
+            assert!(ConstantFormat::from_str("aya").is_err());
+        }
+    }
+
+    mod build_constants {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+
+            let options = BuildOptions::new()
+                .with_constant_format(ConstantFormat::Yaya)
+                .with_separator("_");
+            let result = build_constants(&root, &options).unwrap();
+            assert!(result.starts_with("#define 通常顔_こっち目_前手 111\n"));
+            assert!(result.ends_with("#define 腕上げ_あっち目 1022\n"));
+
+            let options = BuildOptions::new()
+                .with_constant_format(ConstantFormat::Satori)
+                .with_constant_template("{pose.目}".parse().unwrap());
+            assert!(matches!(
+                build_constants(&root, &options),
+                Err(ProcessError::DuplicateConstant { .. })
+            ));
+
+            let options =
+                BuildOptions::new().with_constant_template("{names}{pose.顔色}".parse().unwrap());
+            assert!(matches!(
+                build_constants(&root, &options),
+                Err(ProcessError::UnknownGroup { character: 1, .. })
+            ));
+        }
+    }
+}
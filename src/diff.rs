@@ -0,0 +1,248 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    ast::Root, charset::Charset, error::ProcessError, list::list_surfaces, options::BuildOptions,
+};
+
+const UTF8_BOM: &str = "\u{FEFF}";
+
+/// Surface number with the label written in surfacetable.txt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceLabel {
+    character: usize,
+    number: usize,
+    label: String,
+}
+
+/// Differences between two sets of surfaces.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SurfaceDiff {
+    added: Vec<SurfaceLabel>,
+    removed: Vec<SurfaceLabel>,
+    changed: Vec<(SurfaceLabel, SurfaceLabel)>,
+    renumbered: Vec<(SurfaceLabel, SurfaceLabel)>,
+}
+
+impl SurfaceLabel {
+    /// Creates a new label.
+    pub fn new(character: usize, number: usize, label: String) -> SurfaceLabel {
+        SurfaceLabel {
+            character,
+            number,
+            label,
+        }
+    }
+
+    /// Index of the character.
+    pub fn character(&self) -> usize {
+        self.character
+    }
+
+    /// Surface number.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Names of poses joined by the separator.
+    pub fn label(&self) -> &String {
+        &self.label
+    }
+}
+
+impl SurfaceDiff {
+    /// Surfaces only in the new one.
+    pub fn added(&self) -> &Vec<SurfaceLabel> {
+        &self.added
+    }
+
+    /// Surfaces only in the old one.
+    pub fn removed(&self) -> &Vec<SurfaceLabel> {
+        &self.removed
+    }
+
+    /// Surface numbers whose labels are different, as pairs of old and new.
+    pub fn changed(&self) -> &Vec<(SurfaceLabel, SurfaceLabel)> {
+        &self.changed
+    }
+
+    /// Labels whose surface numbers are different, as pairs of old and new.
+    pub fn renumbered(&self) -> &Vec<(SurfaceLabel, SurfaceLabel)> {
+        &self.renumbered
+    }
+
+    /// Whether some existing surface numbers point at other combinations.
+    pub fn is_shifted(&self) -> bool {
+        !self.changed.is_empty() || !self.renumbered.is_empty()
+    }
+}
+
+/// Labels of the surfaces built from `root`.
+pub fn label_surfaces(
+    root: &Root,
+    options: &BuildOptions,
+) -> Result<Vec<SurfaceLabel>, ProcessError> {
+    Ok(list_surfaces(root, options)?
+        .into_iter()
+        .map(|v| {
+            SurfaceLabel::new(
+                v.character(),
+                v.number(),
+                v.names().join(options.separator()),
+            )
+        })
+        .collect())
+}
+
+/// Reads the labels of surfacetable.txt, decoding it with the `charset` line.
+pub fn read_surfacetable(path: &Path) -> Result<Vec<SurfaceLabel>, ProcessError> {
+    let bytes = std::fs::read(path).map_err(|e| ProcessError::File(path.to_path_buf(), e))?;
+
+    let charset = bytes
+        .split(|v| *v == b'\n')
+        .find_map(|line| {
+            let line = String::from_utf8_lossy(line);
+            line.trim_start_matches(UTF8_BOM)
+                .trim()
+                .strip_prefix("charset,")
+                .map(|v| v.parse::<Charset>())
+        })
+        .unwrap_or(Ok(Charset::Utf8))
+        .unwrap_or(Charset::Utf8);
+
+    Ok(parse_surfacetable(&charset.decode(&bytes)))
+}
+
+/// Parses the labels of surfacetable.txt.
+pub fn parse_surfacetable(contents: &str) -> Vec<SurfaceLabel> {
+    let mut result = Vec::new();
+    let mut character = 0;
+
+    for line in contents.lines() {
+        let line = line.trim_start_matches(UTF8_BOM).trim();
+        let (key, value) = match line.split_once(',') {
+            Some(v) => v,
+            None => continue,
+        };
+
+        if key == "scope" {
+            if let Ok(v) = value.trim().parse::<usize>() {
+                character = v;
+            }
+        } else if let Ok(number) = key.parse::<usize>() {
+            result.push(SurfaceLabel::new(character, number, value.to_string()));
+        }
+    }
+
+    result
+}
+
+/// Compares the surfaces of `old` and `new`.
+pub fn diff_surfaces(old: &[SurfaceLabel], new: &[SurfaceLabel]) -> SurfaceDiff {
+    let key = |v: &SurfaceLabel| (v.character, v.number);
+    let old_numbers: HashMap<(usize, usize), &SurfaceLabel> =
+        old.iter().map(|v| (key(v), v)).collect();
+    let new_numbers: HashMap<(usize, usize), &SurfaceLabel> =
+        new.iter().map(|v| (key(v), v)).collect();
+
+    let mut diff = SurfaceDiff::default();
+    for surface in old.iter() {
+        if let Some(v) = new_numbers.get(&key(surface)) {
+            if v.label != surface.label {
+                diff.changed.push((surface.clone(), (*v).clone()));
+            }
+        }
+    }
+
+    let only_old: Vec<&SurfaceLabel> = old
+        .iter()
+        .filter(|v| !new_numbers.contains_key(&key(v)))
+        .collect();
+    let mut only_new: Vec<&SurfaceLabel> = new
+        .iter()
+        .filter(|v| !old_numbers.contains_key(&key(v)))
+        .collect();
+
+    for surface in only_old {
+        let moved = only_new
+            .iter()
+            .position(|v| v.character == surface.character && v.label == surface.label);
+        match moved {
+            Some(index) => {
+                let v = only_new.remove(index);
+                diff.renumbered.push((surface.clone(), v.clone()));
+            }
+            None => diff.removed.push(surface.clone()),
+        }
+    }
+    diff.added = only_new.into_iter().cloned().collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_surfacetable {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let result =
+                parse_surfacetable(include_str!("../test_target/test-001_surfacetable.txt"));
+            assert_eq!(result.len(), 12);
+            assert_eq!(
+                result[0],
+                SurfaceLabel::new(0, 111, "通常顔-こっち目-前手".to_string())
+            );
+            assert_eq!(
+                result[8],
+                SurfaceLabel::new(1, 1011, "通常-こっち目".to_string())
+            );
+        }
+    }
+
+    mod diff_surfaces {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let old = vec![
+                SurfaceLabel::new(0, 11, "a".to_string()),
+                SurfaceLabel::new(0, 12, "b".to_string()),
+                SurfaceLabel::new(0, 13, "c".to_string()),
+                SurfaceLabel::new(0, 14, "d".to_string()),
+            ];
+            let new = vec![
+                SurfaceLabel::new(0, 11, "a".to_string()),
+                SurfaceLabel::new(0, 12, "x".to_string()),
+                SurfaceLabel::new(0, 15, "c".to_string()),
+                SurfaceLabel::new(0, 16, "e".to_string()),
+            ];
+
+            let result = diff_surfaces(&old, &new);
+            assert_eq!(result.changed(), &vec![(old[1].clone(), new[1].clone())]);
+            assert_eq!(result.renumbered(), &vec![(old[2].clone(), new[2].clone())]);
+            assert_eq!(result.removed(), &vec![old[3].clone()]);
+            assert_eq!(result.added(), &vec![new[3].clone()]);
+            assert!(result.is_shifted());
+
+            assert!(!diff_surfaces(&old, &old).is_shifted());
+        }
+    }
+
+    mod label_surfaces {
+        use super::*;
+
+        use crate::process::parse_yaml;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let built = label_surfaces(&root, &BuildOptions::new()).unwrap();
+            let written =
+                parse_surfacetable(include_str!("../test_target/test-001_surfacetable.txt"));
+            assert_eq!(diff_surfaces(&written, &built), SurfaceDiff::default());
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    charset::Charset,
+    locate::{Segment, Source},
+};
+
+/// Error while reading YAML or writing the results.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProcessError {
+    Io(std::io::Error),
+    /// Failed to read or write the file.
+    File(PathBuf, std::io::Error),
+    Serde(serde_yml::Error),
+    Json(serde_json::Error),
+    Toml(toml::ser::Error),
+    /// Input is not valid.
+    Parse(ParseError),
+    /// Surface numbers of the character exceed `usize`.
+    Overflow {
+        character: usize,
+    },
+    /// Rule refers to a pose which does not exist.
+    UnknownPose {
+        character: usize,
+        reference: String,
+    },
+    /// Surface numbers of two characters overlap.
+    OffsetOverlap {
+        characters: (usize, usize),
+        ranges: ((usize, usize), (usize, usize)),
+    },
+    /// Output contains a character which the charset cannot represent.
+    Unencodable {
+        charset: Charset,
+        character: char,
+        line: usize,
+        text: String,
+    },
+    /// Token in the whitelist file is not valid.
+    InvalidWhitelist {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// Filter of pose names refers to a group which no character has.
+    UnknownFilterGroup {
+        group: String,
+    },
+    /// Constant name template refers to a group which the character does not have.
+    UnknownGroup {
+        character: usize,
+        group: String,
+    },
+    /// Two surfaces have the same constant name.
+    DuplicateConstant {
+        name: String,
+        numbers: (usize, usize),
+    },
+    /// Error in a file included from `path`.
+    Included {
+        path: PathBuf,
+        error: Box<ProcessError>,
+    },
+    /// Files include each other.
+    IncludeCycle {
+        paths: Vec<PathBuf>,
+    },
+    /// Part refers to a group which `groups` does not have.
+    UnknownLibraryGroup {
+        character: usize,
+        key: String,
+    },
+    /// Template variable in a text cannot be expanded.
+    Template {
+        character: usize,
+        surface: usize,
+        message: String,
+    },
+    /// Error in a character defined in the file at `path`, with its line and column if found.
+    InFile {
+        path: PathBuf,
+        location: Option<(usize, usize)>,
+        line: Option<String>,
+        error: Box<ProcessError>,
+    },
+    /// Character has both `file` and its own definitions, including `offset`.
+    ConflictingFile {
+        path: Option<PathBuf>,
+        character: usize,
+    },
+}
+
+impl From<std::io::Error> for ProcessError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_yml::Error> for ProcessError {
+    fn from(value: serde_yml::Error) -> Self {
+        Self::Serde(value)
+    }
+}
+
+impl From<serde_json::Error> for ProcessError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<toml::ser::Error> for ProcessError {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::Toml(value)
+    }
+}
+
+impl From<ParseError> for ProcessError {
+    fn from(value: ParseError) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::File(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::Serde(e) => write!(f, "{}", e),
+            Self::Json(e) => write!(f, "{}", e),
+            Self::Toml(e) => write!(f, "{}", e),
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Overflow { character } => {
+                write!(f, "surface numbers of \\{} are too large", character)
+            }
+            Self::UnknownPose {
+                character,
+                reference,
+            } => write!(
+                f,
+                "rule of \\{} refers to unknown pose: {}",
+                character, reference
+            ),
+            Self::OffsetOverlap { characters, ranges } => write!(
+                f,
+                "surfaces of \\{} ({}-{}) and \\{} ({}-{}) overlap",
+                characters.0, ranges.0 .0, ranges.0 .1, characters.1, ranges.1 .0, ranges.1 .1
+            ),
+            Self::Unencodable {
+                charset,
+                character,
+                line,
+                text,
+            } => write!(
+                f,
+                "'{}' (U+{:04X}) at line {} of the output cannot be encoded in {}: {}",
+                character, *character as u32, line, charset, text
+            ),
+            Self::InvalidWhitelist {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Self::UnknownFilterGroup { group } => {
+                write!(f, "filter refers to unknown group: {}", group)
+            }
+            Self::UnknownGroup { character, group } => write!(
+                f,
+                "constant name template refers to unknown group of \\{}: {}",
+                character, group
+            ),
+            Self::DuplicateConstant { name, numbers } => write!(
+                f,
+                "constant name {} is used for both {} and {}",
+                name, numbers.0, numbers.1
+            ),
+            Self::Included { path, error } => {
+                write!(f, "{}\n  included from {}", error, path.display())
+            }
+            Self::InFile {
+                path,
+                location,
+                line,
+                error,
+            } => write_located(
+                f,
+                &path.display().to_string(),
+                *location,
+                line.as_deref(),
+                error,
+            ),
+            Self::IncludeCycle { paths } => write!(
+                f,
+                "files include each other: {}",
+                paths
+                    .iter()
+                    .map(|v| v.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            ),
+            Self::UnknownLibraryGroup { character, key } => write!(
+                f,
+                "\\{} uses a group not defined in groups: {}",
+                character, key
+            ),
+            Self::Template {
+                character,
+                surface,
+                message,
+            } => write!(
+                f,
+                "template for surface {} of \\{} is invalid: {}",
+                surface, character, message
+            ),
+            Self::ConflictingFile { path, character } => write!(
+                f,
+                "{}: characters[{}] has file, so it cannot have base, offset, parts or rules",
+                path.as_ref()
+                    .map(|v| v.display().to_string())
+                    .unwrap_or_else(|| "<input>".to_string()),
+                character
+            ),
+        }
+    }
+}
+
+impl ProcessError {
+    /// Wraps the error with the file of the character it is in, if any.
+    ///
+    /// The location is the value at `target` in the character, or none if `target` is empty
+    /// or not written in the file.
+    pub(crate) fn in_source(self, source: Option<&Source>, target: &[Segment]) -> ProcessError {
+        let source = match source {
+            Some(v) => v,
+            None => return self,
+        };
+
+        let (location, line) = match source.locate(target) {
+            Some((location, line)) => (Some(location), Some(line)),
+            None => (None, None),
+        };
+
+        Self::InFile {
+            path: source.path().clone(),
+            location,
+            line,
+            error: Box::new(self),
+        }
+    }
+
+    /// Error without the file added by [`ProcessError::in_source`].
+    pub(crate) fn without_file(&self) -> &ProcessError {
+        match self {
+            Self::InFile { error, .. } => error,
+            _ => self,
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::File(_, e) => Some(e),
+            Self::Serde(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Toml(e) => Some(e),
+            Self::Parse(e) => Some(e),
+            Self::Included { error, .. } | Self::InFile { error, .. } => Some(error.as_ref()),
+            Self::Overflow { .. }
+            | Self::UnknownPose { .. }
+            | Self::OffsetOverlap { .. }
+            | Self::Unencodable { .. }
+            | Self::InvalidWhitelist { .. }
+            | Self::UnknownFilterGroup { .. }
+            | Self::UnknownGroup { .. }
+            | Self::DuplicateConstant { .. }
+            | Self::UnknownLibraryGroup { .. }
+            | Self::Template { .. }
+            | Self::IncludeCycle { .. }
+            | Self::ConflictingFile { .. } => None,
+        }
+    }
+}
+
+/// Error in an input file, with its location.
+#[derive(Debug)]
+pub struct ParseError {
+    path: Option<PathBuf>,
+    message: String,
+    location: Option<(usize, usize)>,
+    line: Option<String>,
+}
+
+impl ParseError {
+    pub(crate) fn new(path: Option<&Path>, source: &str, error: serde_yml::Error) -> ParseError {
+        let location = error.location().map(|v| (v.line(), v.column()));
+
+        let mut message = error.to_string();
+        if let Some((line, column)) = location {
+            let suffix = format!(" at line {} column {}", line, column);
+            if let Some(v) = message.strip_suffix(&suffix) {
+                message = v.to_string();
+            }
+        }
+        let message = message
+            .replace(".\\[", "[")
+            .replace("\\[", "[")
+            .replace("\\]", "]");
+
+        ParseError::located(path, source, message, location)
+    }
+
+    pub(crate) fn from_json(
+        path: Option<&Path>,
+        source: &str,
+        error: serde_json::Error,
+    ) -> ParseError {
+        // Columns of serde_json count bytes, not characters.
+        let location = Some((error.line(), error.column()))
+            .filter(|(line, _)| *line > 0)
+            .map(|(line, column)| {
+                let text = source.lines().nth(line - 1).unwrap_or_default();
+                let prefix = text.get(..column.saturating_sub(1)).unwrap_or(text);
+                (line, prefix.chars().count() + 1)
+            });
+
+        let message = error.to_string();
+        let suffix = format!(" at line {} column {}", error.line(), error.column());
+        let message = message
+            .strip_suffix(&suffix)
+            .unwrap_or(&message)
+            .to_string();
+
+        ParseError::located(path, source, message, location)
+    }
+
+    pub(crate) fn from_toml(
+        path: Option<&Path>,
+        source: &str,
+        error: toml::de::Error,
+    ) -> ParseError {
+        let location = error.span().map(|span| {
+            let before = &source[..span.start.min(source.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before
+                .rsplit('\n')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count()
+                + 1;
+            (line, column)
+        });
+
+        ParseError::located(path, source, error.message().to_string(), location)
+    }
+
+    /// Error at the value at `target` in the file of a character.
+    pub(crate) fn in_source(source: &Source, target: &[Segment], message: String) -> ParseError {
+        let (location, line) = match source.locate(target) {
+            Some((location, line)) => (Some(location), Some(line)),
+            None => (None, None),
+        };
+
+        ParseError {
+            path: Some(source.path().clone()),
+            message,
+            location,
+            line,
+        }
+    }
+
+    fn located(
+        path: Option<&Path>,
+        source: &str,
+        message: String,
+        location: Option<(usize, usize)>,
+    ) -> ParseError {
+        let line = location.and_then(|(line, _)| source.lines().nth(line.saturating_sub(1)));
+
+        ParseError {
+            path: path.map(|v| v.to_path_buf()),
+            message,
+            location,
+            line: line.map(|v| v.to_string()),
+        }
+    }
+
+    /// Path to the file, if read from a file.
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    /// Description of the error.
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    /// Line and column, starting from 1.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        self.location
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self
+            .path
+            .as_ref()
+            .map(|v| v.display().to_string())
+            .unwrap_or_else(|| "<input>".to_string());
+
+        write_located(f, &path, self.location, self.line.as_deref(), &self.message)
+    }
+}
+
+/// Writes `message` after the path and the location, followed by the line pointing the column.
+fn write_located(
+    f: &mut std::fmt::Formatter<'_>,
+    path: &str,
+    location: Option<(usize, usize)>,
+    source_line: Option<&str>,
+    message: &dyn Display,
+) -> std::fmt::Result {
+    let (line, column) = match location {
+        Some(v) => v,
+        None => return write!(f, "{}: {}", path, message),
+    };
+    write!(f, "{}:{}:{}: {}", path, line, column, message)?;
+
+    if let Some(source_line) = source_line {
+        let width = line.to_string().len();
+        let padding: String = source_line
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(
+            f,
+            "\n{:width$} |\n{} | {}\n{:width$} | {}^",
+            "",
+            line,
+            source_line,
+            "",
+            padding,
+            width = width
+        )?;
+    }
+
+    Ok(())
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_error {
+        use super::*;
+
+        use crate::ast::Root;
+
+        #[test]
+        fn checking_value() {
+            let case = r#"characters:
+  - parts:
+      - group: eyes
+        details:
+          - name: open
+            text: |
+              element0
+          - name: closed
+"#;
+            let error = serde_yml::from_str::<Root>(case).unwrap_err();
+            let result = ParseError::new(Some(Path::new("surfaces.yaml")), case, error);
+            assert_eq!(result.location(), Some((8, 13)));
+            assert_eq!(
+                result.to_string(),
+                r#"surfaces.yaml:8:13: characters[0].parts[0].details[1]: missing field `text`
+  |
+8 |           - name: closed
+  |             ^"#
+            );
+        }
+
+        #[test]
+        fn checking_value_without_path() {
+            let error = serde_yml::from_str::<Root>("").unwrap_err();
+            let result = ParseError::new(None, "", error);
+            assert_eq!(
+                result.to_string(),
+                "<input>:1:1: missing field `characters`"
+            );
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use crate::{
+    ast::{Character, Root},
+    error::ProcessError,
+    options::BuildOptions,
+    process::CharacterSurfaces,
+    rules::surface_poses,
+};
+
+const CONDITION_SEPARATOR: char = ',';
+const GROUP_SEPARATOR: char = '=';
+
+/// Filter of surfaces by the names of poses.
+///
+/// Conditions are separated by comma, and all of them must match.
+/// A condition is `group=pattern` for the pose of the group,
+/// or `pattern` for the names joined by the separator.
+/// In patterns, `*` matches any string and `?` matches any character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameFilter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Pose { group: String, pattern: String },
+    Category(String),
+}
+
+impl FromStr for NameFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Vec::new();
+
+        for (index, element) in s.split(CONDITION_SEPARATOR).enumerate() {
+            let element = element.trim();
+            if element.is_empty() {
+                return Err(format!("Filter is invalid: element: {}: empty", index));
+            }
+
+            let condition = match element.split_once(GROUP_SEPARATOR) {
+                Some((group, pattern)) => Condition::Pose {
+                    group: group.to_string(),
+                    pattern: pattern.to_string(),
+                },
+                None => Condition::Category(element.to_string()),
+            };
+            conditions.push(condition);
+        }
+
+        Ok(NameFilter { conditions })
+    }
+}
+
+impl NameFilter {
+    fn matches(&self, poses: &[(&str, &str)], separator: &str) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Pose { group, pattern } => poses
+                .iter()
+                .any(|(g, pose)| g == group && matches_pattern(pattern, pose)),
+            Condition::Category(pattern) => {
+                let category: Vec<&str> = poses.iter().map(|(_, pose)| *pose).collect();
+                matches_pattern(pattern, &category.join(separator))
+            }
+        })
+    }
+}
+
+/// Fails if a condition refers to a group which no character has.
+pub(crate) fn validate_filters(root: &Root, options: &BuildOptions) -> Result<(), ProcessError> {
+    let filters = options
+        .name_whitelist()
+        .iter()
+        .chain(options.name_blacklist().iter());
+    for condition in filters.flat_map(|filter| filter.conditions.iter()) {
+        if let Condition::Pose { group, .. } = condition {
+            let exists = root
+                .characters()
+                .iter()
+                .any(|character| character.parts().iter().any(|v| v.group() == group));
+            if !exists {
+                return Err(ProcessError::UnknownFilterGroup {
+                    group: group.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn filter_surfaces_by_names(
+    character: &Character,
+    surfaces: CharacterSurfaces,
+    options: &BuildOptions,
+) -> CharacterSurfaces {
+    if options.name_whitelist().is_empty() && options.name_blacklist().is_empty() {
+        return surfaces;
+    }
+
+    surfaces
+        .into_iter()
+        .filter(|(_, surface_number)| {
+            let poses = surface_poses(character, surface_number);
+            let separator = options.separator();
+
+            (options.name_whitelist().is_empty()
+                || options
+                    .name_whitelist()
+                    .iter()
+                    .any(|filter| filter.matches(&poses, separator)))
+                && !options
+                    .name_blacklist()
+                    .iter()
+                    .any(|filter| filter.matches(&poses, separator))
+        })
+        .collect()
+}
+
+fn matches_pattern(pattern: &str, target: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let target: Vec<char> = target.chars().collect();
+
+    let mut index_pattern = 0;
+    let mut index_target = 0;
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while index_target < target.len() {
+        match pattern.get(index_pattern) {
+            Some('*') => {
+                backtrack = Some((index_pattern, index_target));
+                index_pattern += 1;
+            }
+            Some(c) if *c == '?' || *c == target[index_target] => {
+                index_pattern += 1;
+                index_target += 1;
+            }
+            _ => match backtrack {
+                Some((p, t)) => {
+                    index_pattern = p + 1;
+                    index_target = t + 1;
+                    backtrack = Some((p, t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[index_pattern..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod from_str {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let result = NameFilter::from_str("目=こっち目,顔色=*").unwrap();
+            assert_eq!(
+                result.conditions,
+                vec![
+                    Condition::Pose {
+                        group: "目".to_string(),
+                        pattern: "こっち目".to_string()
+                    },
+                    Condition::Pose {
+                        group: "顔色".to_string(),
+                        pattern: "*".to_string()
+                    },
+                ]
+            );
+
+            let result = NameFilter::from_str("照れ顔-*").unwrap();
+            assert_eq!(
+                result.conditions,
+                vec![Condition::Category("照れ顔-*".to_string())]
+            );
+
+            assert!(NameFilter::from_str("目=こっち目,").is_err());
+        }
+    }
+
+    mod matches {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let poses = [("顔色", "照れ顔"), ("目", "こっち目"), ("腕", "前手")];
+
+            let filter = NameFilter::from_str("目=こっち目,顔色=*").unwrap();
+            assert!(filter.matches(&poses, "-"));
+
+            let filter = NameFilter::from_str("目=あっち目").unwrap();
+            assert!(!filter.matches(&poses, "-"));
+
+            let filter = NameFilter::from_str("腕=*手").unwrap();
+            assert!(filter.matches(&poses, "-"));
+
+            let filter = NameFilter::from_str("照れ顔-*-前手").unwrap();
+            assert!(filter.matches(&poses, "-"));
+            assert!(!filter.matches(&poses, "_"));
+        }
+    }
+
+    mod matches_pattern {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert!(matches_pattern("*", ""));
+            assert!(matches_pattern("a*c", "abbbc"));
+            assert!(matches_pattern("a?c", "abc"));
+            assert!(!matches_pattern("a?c", "ac"));
+            assert!(matches_pattern("*-b-*", "a-b-c"));
+            assert!(!matches_pattern("*-b", "a-b-c"));
+            assert!(matches_pattern("a*b*c", "axbxbxc"));
+        }
+    }
+
+    mod build_surfacetable_with_filters {
+        use super::*;
+
+        use crate::process::{build_surfacetable, parse_yaml};
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let options = BuildOptions::new()
+                .with_name_whitelist(vec![
+                    NameFilter::from_str("目=こっち目").unwrap(),
+                    NameFilter::from_str("通常").unwrap(),
+                ])
+                .with_name_blacklist(vec![NameFilter::from_str("*-胸に手").unwrap()]);
+
+            let result = build_surfacetable(&root, &options).unwrap();
+            assert_eq!(
+                result,
+                r#"charset,UTF-8
+version,1
+
+group,\0
+{
+scope,0
+111,通常顔-こっち目-前手
+211,照れ顔-こっち目-前手
+}
+
+group,\1
+{
+scope,1
+1011,通常-こっち目
+1021,腕上げ-こっち目
+}
+"#
+            );
+        }
+
+        #[test]
+        fn failed_when_unknown_group() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+
+            let options = BuildOptions::new()
+                .with_name_blacklist(vec![NameFilter::from_str("口=*").unwrap()]);
+            assert!(matches!(
+                build_surfacetable(&root, &options),
+                Err(ProcessError::UnknownFilterGroup { group }) if group == "口"
+            ));
+        }
+    }
+}
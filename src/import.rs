@@ -0,0 +1,123 @@
+use crate::{
+    ast::{Character, GroupData, PoseData, Root},
+    diff::SurfaceLabel,
+    lock::{LockEntry, SurfaceLock},
+};
+
+/// Builds a skeleton of [`Root`] from the surfaces of an existing surfacetable.txt.
+///
+/// Labels are split by `separator` into the poses of groups, and texts are left empty.
+pub fn import_surfacetable(surfaces: &[SurfaceLabel], separator: &str) -> Root {
+    let count = surfaces
+        .iter()
+        .map(|v| v.character() + 1)
+        .max()
+        .unwrap_or_default();
+
+    let characters = (0..count)
+        .map(|index| {
+            let mut labels: Vec<&SurfaceLabel> =
+                surfaces.iter().filter(|v| v.character() == index).collect();
+            labels.sort_by_key(|v| v.number());
+            import_character(&labels, separator)
+        })
+        .collect();
+
+    Root::new(None, characters)
+}
+
+/// Builds a lock keeping the surface numbers of an existing surfacetable.txt.
+///
+/// Building the skeleton of [`import_surfacetable`] with it assigns the same numbers to the same labels.
+pub fn import_lock(surfaces: &[SurfaceLabel], separator: &str) -> SurfaceLock {
+    SurfaceLock::new(
+        surfaces
+            .iter()
+            .map(|v| {
+                let names = v
+                    .label()
+                    .split(separator)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                LockEntry::new(v.character(), names, v.number())
+            })
+            .collect(),
+    )
+}
+
+fn import_character(labels: &[&SurfaceLabel], separator: &str) -> Character {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    for label in labels.iter() {
+        for (index, name) in label.label().split(separator).enumerate() {
+            if groups.len() <= index {
+                groups.push(Vec::new());
+            }
+            if !name.is_empty() && !groups[index].iter().any(|v| v == name) {
+                groups[index].push(name.to_string());
+            }
+        }
+    }
+
+    let parts = groups
+        .into_iter()
+        .enumerate()
+        .map(|(index, names)| {
+            let poses = names
+                .into_iter()
+                .map(|name| PoseData::new(name, String::new()))
+                .collect();
+            GroupData::new(format!("group{}", index + 1), poses)
+        })
+        .collect();
+
+    Character::new(None, parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{diff::parse_surfacetable, options::BuildOptions, process::build_surfacetable};
+
+    mod import_surfacetable {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let surfaces =
+                parse_surfacetable(include_str!("../test_target/test-001_surfacetable.txt"));
+            let root = import_surfacetable(&surfaces, "-");
+
+            assert_eq!(root.characters().len(), 2);
+            let parts = root.characters()[0].parts();
+            assert_eq!(parts.len(), 3);
+            assert_eq!(parts[1].group(), "group2");
+            let names: Vec<&String> = parts[1].details().iter().map(|v| v.name()).collect();
+            assert_eq!(names, vec!["こっち目", "あっち目"]);
+            assert!(parts[1].details()[0].text().is_empty());
+
+            let rebuilt = build_surfacetable(&root, &BuildOptions::new()).unwrap();
+            assert_eq!(parse_surfacetable(&rebuilt), surfaces);
+        }
+    }
+
+    mod import_lock {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let surfaces = parse_surfacetable("charset,UTF-8\n\nscope,0\n15,笑顔\n12,通常\n");
+            let root = import_surfacetable(&surfaces, "-");
+
+            let rebuilt = build_surfacetable(&root, &BuildOptions::new()).unwrap();
+            assert_ne!(parse_surfacetable(&rebuilt), surfaces);
+
+            let options = BuildOptions::new().with_lock(import_lock(&surfaces, "-"));
+            let rebuilt = build_surfacetable(&root, &options).unwrap();
+            let mut expected = surfaces.clone();
+            expected.sort_by_key(|v| v.number());
+            assert_eq!(parse_surfacetable(&rebuilt), expected);
+        }
+    }
+}
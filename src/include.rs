@@ -0,0 +1,386 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{Character, Root},
+    error::ProcessError,
+    input_format::InputFormat,
+    process::read_file,
+};
+
+/// Reads the files referenced by `include` and `file`, keeping the chain of including files.
+struct Loader {
+    stack: Vec<PathBuf>,
+    sources: Vec<PathBuf>,
+}
+
+/// Replaces `include` and `file` of `root` with the contents of the files.
+///
+/// Paths are relative to `path`, or the current directory if `None`.
+pub(crate) fn resolve_includes(root: Root, path: Option<&Path>) -> Result<Root, ProcessError> {
+    let mut loader = Loader {
+        stack: Vec::new(),
+        sources: Vec::new(),
+    };
+    if let Some(path) = path {
+        loader.enter(path)?;
+    }
+
+    let root = loader.resolve_root(root, path)?;
+    Ok(root.with_sources(loader.sources))
+}
+
+impl Loader {
+    fn enter(&mut self, path: &Path) -> Result<(), ProcessError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| ProcessError::File(path.to_path_buf(), e))?;
+
+        if let Some(index) = self.stack.iter().position(|v| *v == canonical) {
+            let mut paths = self.stack[index..].to_vec();
+            paths.push(canonical);
+            return Err(ProcessError::IncludeCycle { paths });
+        }
+
+        self.stack.push(canonical.clone());
+        if !self.sources.contains(&canonical) {
+            self.sources.push(canonical);
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    fn resolve_root(&mut self, mut root: Root, path: Option<&Path>) -> Result<Root, ProcessError> {
+        let directory = directory_of(path);
+
+        let mut raws = Vec::new();
+        let mut offset = root.offset();
+        let mut groups = BTreeMap::new();
+        let mut characters = Vec::new();
+        for include in root.take_include() {
+            let included = self.load(&directory.join(include), path, |loader, v, path| {
+                let root = parse::<Root>(v, path)?;
+                loader.resolve_root(root, Some(path))
+            })?;
+
+            raws.extend(included.raw().cloned());
+            offset = offset.or(included.offset());
+            groups.extend(included.groups().clone());
+            characters.extend(included.characters().iter().cloned());
+        }
+
+        for (index, character) in root.characters().iter().enumerate() {
+            characters.push(self.resolve_character(character.clone(), index, Some(index), path)?);
+        }
+        raws.extend(root.raw().cloned());
+        groups.extend(root.groups().clone());
+
+        let raw = match raws.is_empty() {
+            true => None,
+            false => Some(
+                raws.iter()
+                    .map(|v| v.trim_end_matches('\n'))
+                    .collect::<Vec<&str>>()
+                    .join("\n")
+                    + "\n",
+            ),
+        };
+        let resolved = Root::new(raw, characters).with_groups(groups);
+        Ok(match offset {
+            Some(v) => resolved.with_offset(v),
+            None => resolved,
+        })
+    }
+
+    fn resolve_character(
+        &mut self,
+        mut character: Character,
+        index: usize,
+        source_index: Option<usize>,
+        path: Option<&Path>,
+    ) -> Result<Character, ProcessError> {
+        let file = match character.take_file() {
+            Some(v) => v,
+            None => return Ok(character.with_source(path, source_index)),
+        };
+
+        if character.has_definitions() {
+            return Err(ProcessError::ConflictingFile {
+                path: path.map(|v| v.to_path_buf()),
+                character: index,
+            });
+        }
+
+        self.load(&directory_of(path).join(file), path, |loader, v, path| {
+            let character = parse::<Character>(v, path)?;
+            loader.resolve_character(character, index, None, Some(path))
+        })
+    }
+
+    /// Reads and resolves the file at `path`, included from `parent`.
+    fn load<T>(
+        &mut self,
+        path: &Path,
+        parent: Option<&Path>,
+        resolve: impl FnOnce(&mut Loader, &str, &Path) -> Result<T, ProcessError>,
+    ) -> Result<T, ProcessError> {
+        let included = |error| match parent {
+            Some(parent) => ProcessError::Included {
+                path: parent.to_path_buf(),
+                error: Box::new(error),
+            },
+            None => error,
+        };
+
+        self.enter(path).map_err(|e| match e {
+            ProcessError::IncludeCycle { .. } => e,
+            e => included(e),
+        })?;
+        let result = read_file(path).and_then(|v| resolve(self, &v, path));
+        self.leave();
+
+        result.map_err(|e| match e {
+            ProcessError::IncludeCycle { .. } => e,
+            e => included(e),
+        })
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(source: &str, path: &Path) -> Result<T, ProcessError> {
+    Ok(InputFormat::from_path(path).deserialize::<T>(source, Some(path))?)
+}
+
+fn directory_of(path: Option<&Path>) -> PathBuf {
+    path.and_then(|v| v.parent())
+        .map(|v| v.to_path_buf())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        check::check,
+        options::BuildOptions,
+        process::{build_surfacetable, read_yaml},
+    };
+
+    fn write(directory: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = directory.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "surfacetable-mixer-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    const CHARACTER: &str = r#"parts:
+  - group: eyes
+    details:
+      - name: open
+        text: |
+          element0
+"#;
+
+    mod resolve_includes {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let directory = temporary_directory("include");
+            write(&directory, "characters/sakura.yaml", CHARACTER);
+            write(
+                &directory,
+                "characters/kero.yaml",
+                &format!("offset: 5000\n{}", CHARACTER),
+            );
+            write(
+                &directory,
+                "common/raw.yaml",
+                "raw: |\n  descript\ncharacters:\n  - file: ../characters/sakura.yaml\n",
+            );
+            let path = write(
+                &directory,
+                "surfaces.yaml",
+                "include:\n  - common/raw.yaml\nraw: |\n  surface.append0\ncharacters:\n  - file: characters/kero.yaml\n",
+            );
+
+            let root = read_yaml(&path).unwrap();
+            assert_eq!(root.raw().unwrap(), "descript\nsurface.append0\n");
+            assert_eq!(root.characters().len(), 2);
+            assert_eq!(root.characters()[0].offset(), None);
+            assert_eq!(root.characters()[1].offset(), Some(5000));
+            assert_eq!(root.characters()[1].parts()[0].group(), "eyes");
+            assert_eq!(root.sources().len(), 4);
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        #[test]
+        fn failed_when_file_has_definitions() {
+            let directory = temporary_directory("include-conflict");
+            write(&directory, "sakura.yaml", CHARACTER);
+
+            for definition in ["parts: []", "offset: 5000", "rules: []"] {
+                let path = write(
+                    &directory,
+                    "surfaces.yaml",
+                    &format!(
+                        "characters:\n  - parts: []\n  - file: sakura.yaml\n    {}\n",
+                        definition
+                    ),
+                );
+
+                match read_yaml(&path).unwrap_err() {
+                    ProcessError::ConflictingFile { character, .. } => assert_eq!(character, 1),
+                    e => panic!("unexpected error: {}", e),
+                }
+            }
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        #[test]
+        fn failed_when_cyclic() {
+            let directory = temporary_directory("include-cycle");
+            write(&directory, "a.yaml", "include:\n  - b.yaml\n");
+            write(&directory, "b.yaml", "include:\n  - a.yaml\n");
+
+            let result = read_yaml(&directory.join("a.yaml")).unwrap_err();
+            match result {
+                ProcessError::IncludeCycle { paths } => {
+                    let names: Vec<_> = paths.iter().map(|v| v.file_name().unwrap()).collect();
+                    assert_eq!(names, vec!["a.yaml", "b.yaml", "a.yaml"]);
+                }
+                e => panic!("unexpected error: {}", e),
+            }
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        #[test]
+        fn failed_when_included_file_is_invalid() {
+            let directory = temporary_directory("include-invalid");
+            write(&directory, "sakura.yaml", "parts:\n  - group: eyes\n");
+            let path = write(
+                &directory,
+                "surfaces.yaml",
+                "characters:\n  - file: sakura.yaml\n",
+            );
+
+            let result = read_yaml(&path).unwrap_err().to_string();
+            assert!(result.contains("sakura.yaml:2:5: parts[0]: missing field `details`"));
+            assert!(result.ends_with(&format!("included from {}", path.display())));
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        #[test]
+        fn failed_when_included_rule_is_invalid() {
+            let directory = temporary_directory("include-rule");
+            let sakura = write(
+                &directory,
+                "characters/sakura.yaml",
+                &format!(
+                    "{}rules:\n  - pose: closed\n    excludes: [open]\n",
+                    CHARACTER
+                ),
+            );
+            let path = write(
+                &directory,
+                "surfaces.yaml",
+                "characters:\n  - file: characters/sakura.yaml\n",
+            );
+
+            let root = read_yaml(&path).unwrap();
+            assert_eq!(root.characters()[0].source(), Some(&sakura));
+
+            let result = build_surfacetable(&root, &BuildOptions::new()).unwrap_err();
+            assert_eq!(
+                result.to_string(),
+                format!(
+                    "{}:8:11: rule of \\0 refers to unknown pose: closed
+  |
+8 |   - pose: closed
+  |           ^",
+                    sakura.display()
+                )
+            );
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        #[test]
+        fn failed_when_inline_character_is_invalid() {
+            let directory = temporary_directory("include-inline");
+            let path = write(
+                &directory,
+                "surfaces.yaml",
+                r#"characters:
+  - parts:
+      - group: eyes
+        details:
+          - name: closed
+            text: |
+              element0
+    rules:
+      - pose: closed
+        requires: [closed]
+  - parts:
+      - group: eyes
+        details:
+          - name: open
+            text: |
+              element0
+          - name: open
+            text: |
+              element1
+    rules:
+      - pose: closed
+"#,
+            );
+
+            let root = read_yaml(&path).unwrap();
+            let result: Vec<String> = check(&root, &BuildOptions::new())
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(
+                result,
+                vec![
+                    format!(
+                        "error: {}:17:19: group eyes of \\1 has duplicate pose: open
+   |
+17 |           - name: open
+   |                   ^",
+                        path.display()
+                    ),
+                    format!(
+                        "error: {}:21:15: rule of \\1 refers to unknown pose: closed
+   |
+21 |       - pose: closed
+   |               ^",
+                        path.display()
+                    ),
+                ]
+            );
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+    }
+}
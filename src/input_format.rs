@@ -0,0 +1,229 @@
+use std::{path::Path, str::FromStr};
+
+use serde::de::{DeserializeOwned, DeserializeSeed};
+
+use crate::{
+    ast::Root,
+    error::{ParseError, ProcessError},
+    include::resolve_includes,
+    library::expand_groups,
+    locate::{Find, Segment},
+    process::read_file,
+};
+
+/// Format of the input files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Ok(InputFormat::Yaml),
+            "json" => Ok(InputFormat::Json),
+            "toml" => Ok(InputFormat::Toml),
+            _ => Err(format!(
+                "Input format is invalid: {}: expected yaml, json or toml",
+                s
+            )),
+        }
+    }
+}
+
+impl InputFormat {
+    /// Format chosen by the extension of `path`, YAML if unknown.
+    pub fn from_path(path: &Path) -> InputFormat {
+        path.extension()
+            .and_then(|v| v.to_str())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Reads a file in this format into [`Root`].
+    ///
+    /// Included files are read in the format of their own extensions.
+    pub fn read(&self, path: &Path) -> Result<Root, ProcessError> {
+        let buffer = read_file(path)?;
+
+        let root = self.deserialize::<Root>(&buffer, Some(path))?;
+        expand_groups(resolve_includes(root, Some(path))?)
+    }
+
+    /// Parses a string in this format into [`Root`].
+    ///
+    /// Included files are relative to the current directory.
+    pub fn parse(&self, s: &str) -> Result<Root, ProcessError> {
+        let root = self.deserialize::<Root>(s, None)?;
+        expand_groups(resolve_includes(root, None)?)
+    }
+
+    /// Writes `root` in this format.
+    pub fn serialize(&self, root: &Root) -> Result<String, ProcessError> {
+        match self {
+            InputFormat::Yaml => Ok(serde_yml::to_string(root)?),
+            InputFormat::Json => Ok(serde_json::to_string_pretty(root)? + "\n"),
+            InputFormat::Toml => Ok(toml::to_string_pretty(root)?),
+        }
+    }
+
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        &self,
+        source: &str,
+        path: Option<&Path>,
+    ) -> Result<T, ParseError> {
+        match self {
+            InputFormat::Yaml => {
+                serde_yml::from_str(source).map_err(|e| ParseError::new(path, source, e))
+            }
+            InputFormat::Json => {
+                serde_json::from_str(source).map_err(|e| ParseError::from_json(path, source, e))
+            }
+            InputFormat::Toml => {
+                toml::from_str(source).map_err(|e| ParseError::from_toml(path, source, e))
+            }
+        }
+    }
+
+    /// Parses `source` until the value at `target`, failing there with its location.
+    pub(crate) fn find(
+        &self,
+        source: &str,
+        path: &Path,
+        target: &[Segment],
+    ) -> Result<(), ParseError> {
+        let find = Find::new(target);
+        let path = Some(path);
+        match self {
+            InputFormat::Yaml => find
+                .deserialize(serde_yml::Deserializer::from_str(source))
+                .map_err(|e| ParseError::new(path, source, e)),
+            InputFormat::Json => find
+                .deserialize(&mut serde_json::Deserializer::from_str(source))
+                .map_err(|e| ParseError::from_json(path, source, e)),
+            InputFormat::Toml => toml::Deserializer::parse(source)
+                .and_then(|v| find.deserialize(v))
+                .map_err(|e| ParseError::from_toml(path, source, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::options::BuildOptions;
+    use crate::process::{build_surfacetable, parse_yaml};
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let json = r#"{
+  "characters": [
+    {
+      "parts": [
+        {
+          "group": "目",
+          "details": [
+            { "name": "開", "text": "element0,overlay,a.png,0,0\n" },
+            { "name": "閉", "text": "element0,overlay,b.png,0,0\n" }
+          ]
+        }
+      ]
+    }
+  ]
+}"#;
+            let toml = r#"
+[[characters]]
+[[characters.parts]]
+group = "目"
+
+[[characters.parts.details]]
+name = "開"
+text = "element0,overlay,a.png,0,0\n"
+
+[[characters.parts.details]]
+name = "閉"
+text = "element0,overlay,b.png,0,0\n"
+"#;
+            let yaml = r#"
+characters:
+  - parts:
+      - group: 目
+        details:
+          - name: 開
+            text: |
+              element0,overlay,a.png,0,0
+          - name: 閉
+            text: |
+              element0,overlay,b.png,0,0
+"#;
+            let options = BuildOptions::new();
+            let expected = build_surfacetable(&parse_yaml(yaml).unwrap(), &options).unwrap();
+            for (format, case) in [(InputFormat::Json, json), (InputFormat::Toml, toml)] {
+                let root = format.parse(case).unwrap();
+                assert_eq!(build_surfacetable(&root, &options).unwrap(), expected);
+            }
+        }
+
+        #[test]
+        fn failed_when_invalid() {
+            let json = r#"{ "characters": [ { "parts": [ { "group": "目" } ] } ] }"#;
+            let result = InputFormat::Json.parse(json).unwrap_err().to_string();
+            assert!(result.starts_with("<input>:1:"), "{}", result);
+            assert!(result.contains("missing field `details`"), "{}", result);
+
+            let toml = "[[characters]]\n[[characters.parts]]\ngroup = \"目\"\n";
+            let result = InputFormat::Toml.parse(toml).unwrap_err().to_string();
+            assert!(result.starts_with("<input>:2:"), "{}", result);
+            assert!(result.contains("missing field `details`"), "{}", result);
+        }
+    }
+
+    mod serialize {
+        use super::*;
+
+        use crate::diff::parse_surfacetable;
+        use crate::import::import_surfacetable;
+
+        #[test]
+        fn checking_value() {
+            let surfaces =
+                parse_surfacetable(include_str!("../test_target/test-001_surfacetable.txt"));
+            let root = import_surfacetable(&surfaces, "-");
+
+            let options = BuildOptions::new();
+            let expected = build_surfacetable(&root, &options).unwrap();
+            for format in [InputFormat::Yaml, InputFormat::Json, InputFormat::Toml] {
+                let contents = format.serialize(&root).unwrap();
+                let result = format.parse(&contents).unwrap();
+                assert_eq!(build_surfacetable(&result, &options).unwrap(), expected);
+            }
+        }
+    }
+
+    mod from_path {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(
+                InputFormat::from_path(Path::new("a/surfaces.JSON")),
+                InputFormat::Json
+            );
+            assert_eq!(
+                InputFormat::from_path(Path::new("surfaces.toml")),
+                InputFormat::Toml
+            );
+            assert_eq!(InputFormat::from_path(Path::new("-")), InputFormat::Yaml);
+            assert!(InputFormat::from_str("xml").is_err());
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Builds `surfacetable.txt` and `surfaces.txt` for "伺か" from YAML.
+//!
+//! ```
+//! use surfacetable_mixer::{build_surfacetable, parse_yaml, BuildOptions};
+//!
+//! let root = parse_yaml(
+//!     r#"
+//! characters:
+//!   - parts:
+//!     - group: eyes
+//!       details:
+//!         - name: open
+//!           text: element0,overlay,surface100.png,0,0
+//!         - name: closed
+//!           text: element0,overlay,surface101.png,0,0
+//! "#,
+//! )
+//! .unwrap();
+//!
+//! let contents = build_surfacetable(&root, &BuildOptions::new()).unwrap();
+//! assert!(contents.contains("1,open\n2,closed\n"));
+//! ```
+
+mod ast;
+mod charset;
+mod check;
+mod constants;
+mod diff;
+mod error;
+mod filter;
+mod import;
+mod include;
+mod input_format;
+mod library;
+mod line_ending;
+mod list;
+mod locate;
+mod lock;
+mod lookup;
+mod options;
+mod process;
+mod rules;
+mod surfaces;
+mod template;
+mod whitelist;
+
+pub use ast::{Character, GroupData, PoseData, Root};
+pub use charset::Charset;
+pub use check::{check, Diagnostic, Severity};
+pub use constants::{build_constants, write_constants, ConstantFormat, ConstantTemplate};
+pub use diff::{
+    diff_surfaces, label_surfaces, parse_surfacetable, read_surfacetable, SurfaceDiff, SurfaceLabel,
+};
+pub use error::{ParseError, ProcessError};
+pub use filter::NameFilter;
+pub use import::{import_lock, import_surfacetable};
+pub use input_format::InputFormat;
+pub use line_ending::LineEnding;
+pub use list::{list_surfaces, EntryPose, SurfaceEntry};
+pub use lock::{read_lock, update_lock, write_lock, LockEntry, LockUpdate, SurfaceLock};
+pub use lookup::{lookup_names, lookup_number, PoseDetail, SurfaceDetail};
+pub use options::BuildOptions;
+pub use process::{build_surfacetable, parse_yaml, read_yaml, write_surfacetable};
+pub use rules::Rule;
+pub use surfaces::{build_surfaces, write_surfaces};
+pub use whitelist::{read_whitelist, Whitelist};
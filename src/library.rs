@@ -0,0 +1,122 @@
+use crate::{
+    ast::{GroupData, PoseData, Root},
+    error::ProcessError,
+    locate::Segment,
+};
+
+/// Replaces the parts referring to `groups` of `root` with the definitions in it.
+///
+/// Poses written in the part replace the ones with the same name, or are appended.
+pub(crate) fn expand_groups(mut root: Root) -> Result<Root, ProcessError> {
+    let library = root.groups().clone();
+
+    for (index, character) in root.characters_mut().iter_mut().enumerate() {
+        let source = character.origin().cloned();
+        for (index_part, part) in character.parts_mut().iter_mut().enumerate() {
+            let key = match part.take_library() {
+                Some(v) => v,
+                None => continue,
+            };
+            let shared = library.get(&key).ok_or_else(|| {
+                ProcessError::UnknownLibraryGroup {
+                    character: index,
+                    key: key.clone(),
+                }
+                .in_source(
+                    source.as_ref(),
+                    &[
+                        Segment::Key("parts"),
+                        Segment::Index(index_part),
+                        Segment::Key("use"),
+                    ],
+                )
+            })?;
+
+            let group = [part.group(), shared.group()]
+                .into_iter()
+                .find(|v| !v.is_empty())
+                .cloned()
+                .unwrap_or(key);
+            *part = GroupData::new(group, merge_poses(shared.details(), part.details()));
+        }
+    }
+
+    Ok(root)
+}
+
+fn merge_poses(shared: &[PoseData], overrides: &[PoseData]) -> Vec<PoseData> {
+    let mut poses = shared.to_vec();
+    for pose in overrides.iter() {
+        match poses.iter_mut().find(|v| v.name() == pose.name()) {
+            Some(v) => *v = pose.clone(),
+            None => poses.push(pose.clone()),
+        }
+    }
+    poses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::parse_yaml;
+
+    mod expand_groups {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let case = r#"
+groups:
+  目:
+    details:
+      - name: こっち目
+        text: |
+          element0,overlay,surface200.png,0,0
+      - name: あっち目
+        text: |
+          element0,overlay,surface201.png,0,0
+characters:
+  - parts:
+      - use: 目
+  - parts:
+      - use: 目
+        group: 瞳
+        details:
+          - name: あっち目
+            text: |
+              element0,overlay,surface301.png,0,0
+          - name: 閉じ目
+            text: |
+              element0,overlay,surface302.png,0,0
+"#;
+            let root = parse_yaml(case).unwrap();
+
+            let sakura = &root.characters()[0].parts()[0];
+            assert_eq!(sakura.group(), "目");
+            assert_eq!(sakura.details().len(), 2);
+
+            let kero = &root.characters()[1].parts()[0];
+            assert_eq!(kero.group(), "瞳");
+            let names: Vec<&String> = kero.details().iter().map(|v| v.name()).collect();
+            assert_eq!(names, vec!["こっち目", "あっち目", "閉じ目"]);
+            assert_eq!(
+                kero.details()[1].text(),
+                "element0,overlay,surface301.png,0,0\n"
+            );
+        }
+
+        #[test]
+        fn failed_when_unknown_key() {
+            let case = r#"
+characters:
+  - parts:
+      - use: 目
+"#;
+            assert!(matches!(
+                parse_yaml(case),
+                Err(ProcessError::UnknownLibraryGroup { character: 0, .. })
+            ));
+        }
+    }
+}
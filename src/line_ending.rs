@@ -0,0 +1,65 @@
+use std::str::FromStr;
+
+/// Line ending of the generated files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF on the others.
+    Native,
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "native" => Ok(LineEnding::Native),
+            _ => Err(format!(
+                "Line ending is invalid: {}: expected lf, crlf or native",
+                s
+            )),
+        }
+    }
+}
+
+impl LineEnding {
+    /// Characters of the line ending.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Native if cfg!(windows) => "\r\n",
+            LineEnding::Native => "\n",
+        }
+    }
+
+    /// Replaces all line endings in `contents`.
+    pub fn apply(&self, contents: &str) -> String {
+        let normalized = contents.replace("\r\n", "\n");
+        match self.as_str() {
+            "\n" => normalized,
+            v => normalized.replace('\n', v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod apply {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(LineEnding::Crlf.apply("a\nb\r\nc"), "a\r\nb\r\nc");
+            assert_eq!(LineEnding::Lf.apply("a\nb\r\nc"), "a\nb\nc");
+            assert!(LineEnding::from_str("CRLF").is_ok());
+            assert!(LineEnding::from_str("cr").is_err());
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Serialize;
+
+use crate::{
+    ast::{Character, Root},
+    error::ProcessError,
+    options::BuildOptions,
+    process::{enumerate_surfaces, SurfaceNumber},
+};
+
+/// Surface output by a build.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SurfaceEntry {
+    character: usize,
+    number: usize,
+    names: Vec<String>,
+    poses: Vec<EntryPose>,
+}
+
+/// Pose of a group in a [`SurfaceEntry`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EntryPose {
+    group: String,
+    name: String,
+    index: usize,
+    digits: (usize, usize),
+}
+
+impl SurfaceEntry {
+    pub(crate) fn new(
+        character_index: usize,
+        character: &Character,
+        number: usize,
+        surface_number: &SurfaceNumber,
+    ) -> SurfaceEntry {
+        let mut poses = Vec::new();
+        let mut lowest: usize = surface_number.iter().map(|v| v.digits).sum();
+        for (part, group_data) in surface_number.iter().zip(character.parts().iter()) {
+            lowest -= part.digits;
+            if let Some(pose_data) = group_data.details().get(part.number - 1) {
+                poses.push(EntryPose {
+                    group: group_data.group().clone(),
+                    name: pose_data.name().clone(),
+                    index: part.number,
+                    digits: (lowest + 1, lowest + part.digits),
+                });
+            }
+        }
+
+        SurfaceEntry {
+            character: character_index,
+            number,
+            names: poses.iter().map(|v| v.name.clone()).collect(),
+            poses,
+        }
+    }
+
+    /// Index of the character.
+    pub fn character(&self) -> usize {
+        self.character
+    }
+
+    /// Surface number.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Names of poses in order of the groups.
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
+    }
+
+    /// Poses in order of the groups.
+    pub fn poses(&self) -> &Vec<EntryPose> {
+        &self.poses
+    }
+}
+
+impl EntryPose {
+    /// Name of the group.
+    pub fn group(&self) -> &String {
+        &self.group
+    }
+
+    /// Name of the pose.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Index of the pose in the group, starting from 1.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Lowest and highest digits of the pose in the surface number, counted from 1 at the right.
+    ///
+    /// Offsets and locked numbers are not considered.
+    pub fn digits(&self) -> (usize, usize) {
+        self.digits
+    }
+}
+
+/// Lists the surfaces output by [`crate::build_surfacetable`] with the same options.
+pub fn list_surfaces(
+    root: &Root,
+    options: &BuildOptions,
+) -> Result<Vec<SurfaceEntry>, ProcessError> {
+    let surfaces = enumerate_surfaces(root, options)?;
+
+    Ok(root
+        .characters()
+        .iter()
+        .zip(surfaces.iter())
+        .enumerate()
+        .flat_map(|(index, (character, character_surfaces))| {
+            character_surfaces
+                .iter()
+                .map(move |(number, surface_number)| {
+                    SurfaceEntry::new(index, character, *number, surface_number)
+                })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod list_surfaces {
+        use super::*;
+
+        use crate::process::parse_yaml;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let options = BuildOptions::new().with_whitelist(vec![111, 1022]);
+
+            let result = list_surfaces(&root, &options).unwrap();
+            assert_eq!(
+                result
+                    .iter()
+                    .map(|v| (v.character(), v.number(), v.names().join("-")))
+                    .collect::<Vec<(usize, usize, String)>>(),
+                vec![
+                    (0, 111, "通常顔-こっち目-前手".to_string()),
+                    (1, 1022, "腕上げ-あっち目".to_string()),
+                ]
+            );
+            assert_eq!(
+                result[0].poses()[1],
+                EntryPose {
+                    group: "目".to_string(),
+                    name: "こっち目".to_string(),
+                    index: 1,
+                    digits: (2, 2),
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    cell::Cell,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::{input_format::InputFormat, process::read_file};
+
+/// Message of the error raised on reaching the target, whose location is taken.
+const FOUND: &str = "target of the location is found";
+
+/// File a character is defined in.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Source {
+    path: PathBuf,
+    /// Index in `characters` of the file, or `None` if the file is the character itself.
+    index: Option<usize>,
+}
+
+/// Key or item leading to the value in a character an error is about.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Segment<'a> {
+    /// Value of the key in a map.
+    Key(&'a str),
+    /// Item at the index in a sequence.
+    Index(usize),
+    /// `key` of the `occurrence`-th item in a sequence whose `key` is `value`.
+    Named {
+        key: &'a str,
+        value: &'a str,
+        occurrence: usize,
+    },
+    /// First string equal to the value, anywhere inside.
+    Text(&'a str),
+}
+
+/// Line and column found, with the text of the line.
+pub(crate) type Location = ((usize, usize), String);
+
+impl Source {
+    pub(crate) fn new(path: &Path, index: Option<usize>) -> Source {
+        Source {
+            path: path.to_path_buf(),
+            index,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Finds the value at `target` in the character by parsing the file again.
+    ///
+    /// `None` if the file no longer has the value, such as when it comes from `groups`.
+    pub(crate) fn locate(&self, target: &[Segment]) -> Option<Location> {
+        if target.is_empty() {
+            return None;
+        }
+
+        let source = read_file(&self.path).ok()?;
+        let mut path = Vec::new();
+        if let Some(index) = self.index {
+            path.extend([Segment::Key("characters"), Segment::Index(index)]);
+        }
+        path.extend_from_slice(target);
+
+        locate_in(&source, &self.path, &path)
+    }
+}
+
+fn locate_in(source: &str, path: &Path, target: &[Segment]) -> Option<Location> {
+    let error = match InputFormat::from_path(path).find(source, path, target) {
+        Ok(()) => return None,
+        Err(e) => e,
+    };
+    if !error.message().contains(FOUND) {
+        return None;
+    }
+
+    let (line, column) = error.location()?;
+    let text = source.lines().nth(line.checked_sub(1)?)?;
+    Some(((line, column), text.to_string()))
+}
+
+/// Seed failing with [`FOUND`] when it reaches the end of the path.
+pub(crate) struct Find<'a> {
+    path: &'a [Segment<'a>],
+}
+
+impl<'a> Find<'a> {
+    pub(crate) fn new(path: &'a [Segment<'a>]) -> Find<'a> {
+        Find { path }
+    }
+
+    fn scalar<E: Error>(&self, text: Option<&str>) -> Result<(), E> {
+        match self.path.first() {
+            None => Err(E::custom(FOUND)),
+            Some(Segment::Text(value)) if text == Some(*value) => Err(E::custom(FOUND)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Find<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Find<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E: Error>(self, _: bool) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_i64<E: Error>(self, _: i64) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_u64<E: Error>(self, _: u64) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_f64<E: Error>(self, _: f64) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<(), E> {
+        self.scalar(Some(v))
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_none<E: Error>(self) -> Result<(), E> {
+        self.scalar(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let (segment, rest) = match self.path.split_first() {
+            Some(v) => v,
+            None => return Err(A::Error::custom(FOUND)),
+        };
+
+        let count = Cell::new(0);
+        let mut index = 0;
+        loop {
+            let found = match segment {
+                Segment::Index(v) if *v == index => seq.next_element_seed(Find::new(rest))?,
+                Segment::Named {
+                    key,
+                    value,
+                    occurrence,
+                } => seq.next_element_seed(FindNamed {
+                    key,
+                    value,
+                    occurrence: *occurrence,
+                    count: &count,
+                })?,
+                Segment::Text(_) => seq.next_element_seed(Find::new(self.path))?,
+                _ => seq.next_element::<IgnoredAny>()?.map(|_| ()),
+            };
+            if found.is_none() {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let (segment, rest) = match self.path.split_first() {
+            Some(v) => v,
+            None => return Err(A::Error::custom(FOUND)),
+        };
+
+        while let Some(key) = map.next_key::<String>()? {
+            match segment {
+                Segment::Key(v) if *v == key => map.next_value_seed(Find::new(rest))?,
+                Segment::Text(_) => map.next_value_seed(Find::new(self.path))?,
+                _ => map.next_value::<IgnoredAny>().map(|_| ())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Seed of an item of [`Segment::Named`], counting the items whose `key` is `value`.
+struct FindNamed<'a> {
+    key: &'a str,
+    value: &'a str,
+    occurrence: usize,
+    count: &'a Cell<usize>,
+}
+
+impl<'de> DeserializeSeed<'de> for FindNamed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FindNamed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key != self.key {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+
+            map.next_value_seed(FindValue {
+                value: self.value,
+                occurrence: self.occurrence,
+                count: self.count,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Seed of the value of [`Segment::Named`], failing on the `occurrence`-th one equal to `value`.
+struct FindValue<'a> {
+    value: &'a str,
+    occurrence: usize,
+    count: &'a Cell<usize>,
+}
+
+impl<'de> DeserializeSeed<'de> for FindValue<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FindValue<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<(), E> {
+        if v == self.value {
+            self.count.set(self.count.get() + 1);
+            if self.count.get() == self.occurrence {
+                return Err(E::custom(FOUND));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod locate_in {
+        use super::*;
+
+        fn duplicate(occurrence: usize) -> [Segment<'static>; 4] {
+            [
+                Segment::Key("parts"),
+                Segment::Index(0),
+                Segment::Key("details"),
+                Segment::Named {
+                    key: "name",
+                    value: "normal",
+                    occurrence,
+                },
+            ]
+        }
+
+        #[test]
+        fn checking_value() {
+            let case = r#"parts:
+  - group: face
+    details:
+      - name: normal
+        text: element0
+      - name: normal_alt
+        text: element0
+      - name: normal
+        text: element0
+      - text: element0
+        name: normal
+rules:
+  - pose: normal_alt
+    requires: [normal]
+"#;
+            let path = Path::new("sakura.yaml");
+
+            assert_eq!(
+                locate_in(case, path, &duplicate(2)),
+                Some(((8, 15), "      - name: normal".to_string()))
+            );
+            assert_eq!(
+                locate_in(case, path, &duplicate(3)),
+                Some(((11, 15), "        name: normal".to_string()))
+            );
+            assert_eq!(locate_in(case, path, &duplicate(4)), None);
+            assert_eq!(
+                locate_in(
+                    case,
+                    path,
+                    &[
+                        Segment::Key("rules"),
+                        Segment::Index(0),
+                        Segment::Text("normal"),
+                    ]
+                ),
+                Some(((14, 16), "    requires: [normal]".to_string()))
+            );
+            assert_eq!(locate_in(case, path, &[Segment::Key("offset")]), None);
+        }
+
+        #[test]
+        fn checking_value_in_one_line() {
+            let target = [
+                Segment::Key("characters"),
+                Segment::Index(1),
+                Segment::Key("offset"),
+            ];
+
+            let yaml = "characters: [{offset: 0}, {parts: [], offset: 10}]";
+            assert_eq!(
+                locate_in(yaml, Path::new("surfaces.yaml"), &target),
+                Some(((1, 47), yaml.to_string()))
+            );
+
+            let json = r#"{"characters": [{"offset": 0}, {"parts": [], "offset": 10}]}"#;
+            assert_eq!(
+                locate_in(json, Path::new("surfaces.json"), &target).map(|v| v.0),
+                Some((1, 57))
+            );
+
+            let toml = "[[characters]]\noffset = 0\n\n[[characters]]\nparts = []\noffset = 10\n";
+            assert_eq!(
+                locate_in(toml, Path::new("surfaces.toml"), &target),
+                Some(((6, 10), "offset = 10".to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    ops::Range,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::Root,
+    error::{ParseError, ProcessError},
+    process::{number_surfaces, read_file, surface_names, CharacterSurfaces},
+};
+
+/// Surface numbers assigned in a previous build.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SurfaceLock {
+    #[serde(default)]
+    surfaces: Vec<LockEntry>,
+}
+
+/// Surface number assigned to a combination of poses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockEntry {
+    character: usize,
+    names: Vec<String>,
+    number: usize,
+    /// Whether the combination is no longer generated. Its number stays reserved.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    retired: bool,
+}
+
+/// Lock after a build, with the differences from the previous one.
+#[derive(Debug, Clone)]
+pub struct LockUpdate {
+    lock: SurfaceLock,
+    added: Vec<LockEntry>,
+    retired: Vec<LockEntry>,
+}
+
+impl SurfaceLock {
+    /// Creates a new lock.
+    pub fn new(surfaces: Vec<LockEntry>) -> SurfaceLock {
+        SurfaceLock { surfaces }
+    }
+
+    /// Assigned surface numbers.
+    pub fn surfaces(&self) -> &Vec<LockEntry> {
+        &self.surfaces
+    }
+
+    /// Entries indexed by the character and the names of poses.
+    fn index(&self) -> HashMap<(usize, Vec<&str>), &LockEntry> {
+        self.surfaces
+            .iter()
+            .map(|v| {
+                (
+                    (v.character, v.names.iter().map(String::as_str).collect()),
+                    v,
+                )
+            })
+            .collect()
+    }
+
+    /// Replaces the numbers of `surfaces` with the locked ones, and gives new combinations
+    /// free numbers below the offset of the next character.
+    pub(crate) fn assign(
+        &self,
+        root: &Root,
+        offsets: &[usize],
+        surfaces: &mut [CharacterSurfaces],
+    ) -> Result<(), ProcessError> {
+        let mut reserved: HashSet<usize> = self.surfaces.iter().map(|v| v.number).collect();
+        let index = self.index();
+
+        let mut pending = Vec::new();
+        for (index_character, (character, character_surfaces)) in root
+            .characters()
+            .iter()
+            .zip(surfaces.iter_mut())
+            .enumerate()
+        {
+            for (index_surface, (number, surface_number)) in
+                character_surfaces.iter_mut().enumerate()
+            {
+                let names = surface_names(character, surface_number);
+                match index.get(&(index_character, names)) {
+                    Some(entry) => *number = entry.number,
+                    None => pending.push((index_character, index_surface)),
+                }
+            }
+        }
+
+        for (index, index_surface) in pending {
+            let mut number = surfaces[index][index_surface].0;
+            if reserved.contains(&number) {
+                let end = offsets
+                    .iter()
+                    .copied()
+                    .filter(|v| *v > offsets[index])
+                    .min()
+                    .unwrap_or(usize::MAX);
+                let max = surfaces[index]
+                    .iter()
+                    .map(|(v, _)| *v)
+                    .max()
+                    .unwrap_or(number);
+                let free = |range: Range<usize>| range.into_iter().find(|v| !reserved.contains(v));
+
+                number = free(max.saturating_add(1)..end)
+                    .or_else(|| free(offsets[index].saturating_add(1)..max.min(end)))
+                    .ok_or_else(|| {
+                        ProcessError::Overflow { character: index }
+                            .in_source(root.characters()[index].origin(), &[])
+                    })?;
+            }
+
+            reserved.insert(number);
+            surfaces[index][index_surface].0 = number;
+        }
+
+        for character_surfaces in surfaces.iter_mut() {
+            character_surfaces.sort_by_key(|(number, _)| *number);
+        }
+
+        // Locked numbers may be in the range of another character if the offsets changed.
+        let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+        for (index, character_surfaces) in surfaces.iter().enumerate() {
+            let range = match (character_surfaces.first(), character_surfaces.last()) {
+                (Some((first, _)), Some((last, _))) => (index, *first, *last),
+                _ => continue,
+            };
+
+            if let Some(other) = ranges
+                .iter()
+                .find(|other| range.1 <= other.2 && other.1 <= range.2)
+            {
+                return Err(ProcessError::OffsetOverlap {
+                    characters: (other.0, range.0),
+                    ranges: ((other.1, other.2), (range.1, range.2)),
+                }
+                .in_source(root.characters()[index].origin(), &[]));
+            }
+            ranges.push(range);
+        }
+
+        Ok(())
+    }
+}
+
+impl LockEntry {
+    /// Creates a new entry.
+    pub fn new(character: usize, names: Vec<String>, number: usize) -> LockEntry {
+        LockEntry {
+            character,
+            names,
+            number,
+            retired: false,
+        }
+    }
+
+    /// Index of the character.
+    pub fn character(&self) -> usize {
+        self.character
+    }
+
+    /// Names of poses in order of the groups.
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
+    }
+
+    /// Assigned surface number.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Whether the combination is no longer generated.
+    pub fn is_retired(&self) -> bool {
+        self.retired
+    }
+
+    fn with_retired(mut self, retired: bool) -> LockEntry {
+        self.retired = retired;
+        self
+    }
+}
+
+impl LockUpdate {
+    /// Lock to save for the next build.
+    pub fn lock(&self) -> &SurfaceLock {
+        &self.lock
+    }
+
+    /// Entries of combinations which are not in the previous lock.
+    pub fn added(&self) -> &Vec<LockEntry> {
+        &self.added
+    }
+
+    /// Entries of the previous lock which are no longer generated.
+    pub fn retired(&self) -> &Vec<LockEntry> {
+        &self.retired
+    }
+}
+
+/// Reads a lock file.
+pub fn read_lock(path: &Path) -> Result<SurfaceLock, ProcessError> {
+    let buffer = read_file(path)?;
+
+    serde_yml::from_str::<SurfaceLock>(&buffer)
+        .map_err(|e| ParseError::new(Some(path), &buffer, e).into())
+}
+
+/// Writes `lock` to `writer`.
+pub fn write_lock<W: Write>(lock: &SurfaceLock, writer: &mut W) -> Result<(), ProcessError> {
+    let contents = serde_yml::to_string(lock)?;
+    writer.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Assigns surface numbers of `root`, keeping the ones in `lock`.
+pub fn update_lock(root: &Root, lock: Option<&SurfaceLock>) -> Result<LockUpdate, ProcessError> {
+    let surfaces = number_surfaces(root, lock)?;
+
+    let mut entries = Vec::new();
+    for (index, (character, character_surfaces)) in
+        root.characters().iter().zip(surfaces.iter()).enumerate()
+    {
+        for (number, surface_number) in character_surfaces.iter() {
+            let names = surface_names(character, surface_number)
+                .into_iter()
+                .map(|v| v.to_string())
+                .collect();
+            entries.push(LockEntry::new(index, names, *number));
+        }
+    }
+
+    let (added, retired) = match lock {
+        Some(previous) => {
+            let index = previous.index();
+            let added = entries
+                .iter()
+                .filter(|entry| {
+                    let names: Vec<&str> = entry.names.iter().map(String::as_str).collect();
+                    index
+                        .get(&(entry.character, names))
+                        .is_none_or(|v| v.retired)
+                })
+                .cloned()
+                .collect();
+
+            let current: HashSet<(usize, &Vec<String>)> =
+                entries.iter().map(|v| (v.character, &v.names)).collect();
+            let retired: Vec<LockEntry> = previous
+                .surfaces
+                .iter()
+                .filter(|entry| !current.contains(&(entry.character, &entry.names)))
+                .cloned()
+                .collect();
+            (added, retired)
+        }
+        None => (entries.clone(), Vec::new()),
+    };
+
+    // Retired entries are kept so that their numbers are not given to other combinations.
+    let mut surfaces = entries;
+    surfaces.extend(retired.iter().map(|v| v.clone().with_retired(true)));
+
+    Ok(LockUpdate {
+        lock: SurfaceLock::new(surfaces),
+        added,
+        retired: retired.into_iter().filter(|v| !v.retired).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ast::{Character, GroupData, PoseData},
+        options::BuildOptions,
+        process::{build_surfacetable, parse_yaml},
+    };
+
+    fn case_root(eyes: &[&str]) -> Root {
+        Root::new(
+            None,
+            vec![Character::new(
+                None,
+                vec![
+                    GroupData::new(
+                        "face".to_string(),
+                        vec![
+                            PoseData::new("normal".to_string(), "element0...".to_string()),
+                            PoseData::new("shy".to_string(), "element0...".to_string()),
+                        ],
+                    ),
+                    GroupData::new(
+                        "eyes".to_string(),
+                        eyes.iter()
+                            .map(|v| PoseData::new(v.to_string(), "element0...".to_string()))
+                            .collect(),
+                    ),
+                ],
+            )],
+        )
+    }
+
+    mod update_lock {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let previous = update_lock(&case_root(&["front", "side"]), None)
+                .unwrap()
+                .lock()
+                .clone();
+            assert_eq!(
+                previous
+                    .surfaces()
+                    .iter()
+                    .map(|v| v.number())
+                    .collect::<Vec<usize>>(),
+                vec![11, 12, 21, 22]
+            );
+
+            let result =
+                update_lock(&case_root(&["front", "closed", "side"]), Some(&previous)).unwrap();
+            assert_eq!(
+                result
+                    .lock()
+                    .surfaces()
+                    .iter()
+                    .map(|v| (v.names().join("-"), v.number()))
+                    .collect::<Vec<(String, usize)>>(),
+                vec![
+                    ("normal-front".to_string(), 11),
+                    ("normal-side".to_string(), 12),
+                    ("shy-front".to_string(), 21),
+                    ("shy-side".to_string(), 22),
+                    ("normal-closed".to_string(), 23),
+                    ("shy-closed".to_string(), 24),
+                ]
+            );
+            assert_eq!(result.added().len(), 2);
+            assert!(result.retired().is_empty());
+
+            let result = update_lock(&case_root(&["front"]), Some(&previous)).unwrap();
+            assert_eq!(
+                result
+                    .retired()
+                    .iter()
+                    .map(|v| v.number())
+                    .collect::<Vec<usize>>(),
+                vec![12, 22]
+            );
+            assert!(result.added().is_empty());
+
+            let retired = result.lock().clone();
+            assert_eq!(
+                retired
+                    .surfaces()
+                    .iter()
+                    .filter(|v| v.is_retired())
+                    .map(|v| v.number())
+                    .collect::<Vec<usize>>(),
+                vec![12, 22]
+            );
+
+            let result = update_lock(&case_root(&["front", "closed"]), Some(&retired)).unwrap();
+            assert_eq!(
+                result
+                    .lock()
+                    .surfaces()
+                    .iter()
+                    .map(|v| (v.names().join("-"), v.number(), v.is_retired()))
+                    .collect::<Vec<(String, usize, bool)>>(),
+                vec![
+                    ("normal-front".to_string(), 11, false),
+                    ("shy-front".to_string(), 21, false),
+                    ("normal-closed".to_string(), 23, false),
+                    ("shy-closed".to_string(), 24, false),
+                    ("normal-side".to_string(), 12, true),
+                    ("shy-side".to_string(), 22, true),
+                ]
+            );
+            assert!(result.retired().is_empty());
+
+            let result = update_lock(&case_root(&["front", "side"]), Some(&retired)).unwrap();
+            assert!(result.lock().surfaces().iter().all(|v| !v.is_retired()));
+            assert_eq!(result.lock().surfaces().len(), 4);
+        }
+
+        #[test]
+        fn avoiding_reserved_numbers() {
+            let previous = SurfaceLock::new(vec![
+                LockEntry::new(0, vec!["normal".to_string(), "side".to_string()], 11),
+                LockEntry::new(0, vec!["shy".to_string(), "side".to_string()], 21),
+            ]);
+
+            let result = update_lock(&case_root(&["front", "side"]), Some(&previous)).unwrap();
+            assert_eq!(
+                result
+                    .lock()
+                    .surfaces()
+                    .iter()
+                    .map(|v| (v.names().join("-"), v.number()))
+                    .collect::<Vec<(String, usize)>>(),
+                vec![
+                    ("normal-side".to_string(), 11),
+                    ("shy-side".to_string(), 21),
+                    ("normal-front".to_string(), 22),
+                    ("shy-front".to_string(), 23),
+                ]
+            );
+        }
+    }
+
+    mod assign {
+        use super::*;
+
+        const CASE: &str = r#"
+characters:
+  - parts:
+      - group: eyes
+        details:
+          - { name: closed, text: element0 }
+          - { name: front, text: element0 }
+          - { name: side, text: element0 }
+  - offset: 10
+    parts:
+      - group: eyes
+        details:
+          - { name: front, text: element0 }
+"#;
+
+        fn entry(character: usize, name: &str, number: usize) -> LockEntry {
+            LockEntry::new(character, vec![name.to_string()], number)
+        }
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(CASE).unwrap();
+            let lock = SurfaceLock::new(vec![entry(0, "front", 1), entry(0, "side", 9)]);
+
+            let result = update_lock(&root, Some(&lock)).unwrap();
+            assert_eq!(
+                result
+                    .lock()
+                    .surfaces()
+                    .iter()
+                    .map(|v| (v.character(), v.names().join("-"), v.number()))
+                    .collect::<Vec<(usize, String, usize)>>(),
+                vec![
+                    (0, "front".to_string(), 1),
+                    (0, "closed".to_string(), 2),
+                    (0, "side".to_string(), 9),
+                    (1, "front".to_string(), 11),
+                ]
+            );
+        }
+
+        #[test]
+        fn failed_when_range_is_full() {
+            let root = parse_yaml(CASE).unwrap();
+            let mut entries: Vec<LockEntry> = (2..10)
+                .map(|v| entry(0, &format!("retired{}", v), v))
+                .collect();
+            entries.extend([entry(0, "front", 1), entry(0, "side", 9)]);
+
+            match update_lock(&root, Some(&SurfaceLock::new(entries))) {
+                Err(ProcessError::Overflow { character }) => assert_eq!(character, 0),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn failed_when_locked_numbers_overlap() {
+            let root = parse_yaml(CASE).unwrap();
+            let lock = SurfaceLock::new(vec![entry(0, "side", 12)]);
+
+            match update_lock(&root, Some(&lock)) {
+                Err(ProcessError::OffsetOverlap { characters, ranges }) => {
+                    assert_eq!(characters, (0, 1));
+                    assert_eq!(ranges, ((1, 12), (11, 11)));
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    mod build_with_lock {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let previous = update_lock(&case_root(&["front", "side"]), None)
+                .unwrap()
+                .lock()
+                .clone();
+
+            let options = BuildOptions::new().with_lock(previous);
+            let result =
+                build_surfacetable(&case_root(&["front", "closed", "side"]), &options).unwrap();
+            assert_eq!(
+                result,
+                r#"charset,UTF-8
+version,1
+
+group,\0
+{
+scope,0
+11,normal-front
+12,normal-side
+21,shy-front
+22,shy-side
+23,normal-closed
+24,shy-closed
+}
+"#
+            );
+        }
+    }
+
+    mod serialise {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let lock = SurfaceLock::new(vec![LockEntry::new(
+                1,
+                vec!["通常".to_string(), "こっち目".to_string()],
+                1011,
+            )]);
+
+            let mut buffer = Vec::new();
+            write_lock(&lock, &mut buffer).unwrap();
+            let result: SurfaceLock = serde_yml::from_slice(&buffer).unwrap();
+            assert_eq!(result, lock);
+
+            let result: SurfaceLock = serde_yml::from_str("{}").unwrap();
+            assert!(result.surfaces().is_empty());
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    ast::{Character, Root},
+    error::ProcessError,
+    list::SurfaceEntry,
+    options::BuildOptions,
+    process::{number_surfaces, surface_names, surface_pose_data, SurfaceNumber},
+};
+
+/// Poses and texts which make up a surface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceDetail {
+    character: usize,
+    number: usize,
+    base: Option<String>,
+    poses: Vec<PoseDetail>,
+}
+
+/// Pose of a group used in a surface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseDetail {
+    group: String,
+    name: String,
+    text: String,
+}
+
+impl SurfaceDetail {
+    /// Index of the character.
+    pub fn character(&self) -> usize {
+        self.character
+    }
+
+    /// Surface number.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Base text of the character.
+    pub fn base(&self) -> Option<&String> {
+        self.base.as_ref()
+    }
+
+    /// Poses in order of the groups.
+    pub fn poses(&self) -> &Vec<PoseDetail> {
+        &self.poses
+    }
+}
+
+impl PoseDetail {
+    /// Name of the group.
+    pub fn group(&self) -> &String {
+        &self.group
+    }
+
+    /// Name of the pose.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Text of the pose.
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+}
+
+/// Finds the surface of `number`, ignoring the whitelist and filters.
+pub fn lookup_number(
+    root: &Root,
+    options: &BuildOptions,
+    number: usize,
+) -> Result<Option<SurfaceDetail>, ProcessError> {
+    let surfaces = number_surfaces(root, options.lock())?;
+
+    for (index, (character, character_surfaces)) in
+        root.characters().iter().zip(surfaces.iter()).enumerate()
+    {
+        let found = character_surfaces.iter().find(|(v, _)| *v == number);
+        if let Some((_, surface_number)) = found {
+            return Ok(Some(SurfaceDetail {
+                character: index,
+                number,
+                base: character.base().cloned(),
+                poses: pose_details(character, surface_number),
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the surfaces combining `names` in order of the groups, ignoring the whitelist and filters.
+pub fn lookup_names(
+    root: &Root,
+    options: &BuildOptions,
+    names: &[String],
+) -> Result<Vec<SurfaceEntry>, ProcessError> {
+    let surfaces = number_surfaces(root, options.lock())?;
+
+    let mut result = Vec::new();
+    for (index, (character, character_surfaces)) in
+        root.characters().iter().zip(surfaces.iter()).enumerate()
+    {
+        for (number, surface_number) in character_surfaces.iter() {
+            let surface_names = surface_names(character, surface_number);
+            if surface_names
+                .iter()
+                .copied()
+                .eq(names.iter().map(String::as_str))
+            {
+                result.push(SurfaceEntry::new(index, character, *number, surface_number));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn pose_details(character: &Character, surface_number: &SurfaceNumber) -> Vec<PoseDetail> {
+    surface_pose_data(character, surface_number)
+        .into_iter()
+        .map(|(group_data, pose_data)| PoseDetail {
+            group: group_data.group().clone(),
+            name: pose_data.name().clone(),
+            text: pose_data.text().clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::parse_yaml;
+
+    mod lookup_number {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+
+            let result = lookup_number(&root, &BuildOptions::new(), 1021)
+                .unwrap()
+                .unwrap();
+            assert_eq!(result.character(), 1);
+            assert_eq!(
+                result
+                    .poses()
+                    .iter()
+                    .map(|v| (v.group().as_str(), v.name().as_str()))
+                    .collect::<Vec<(&str, &str)>>(),
+                vec![("素体", "腕上げ"), ("目", "こっち目")]
+            );
+
+            assert!(lookup_number(&root, &BuildOptions::new(), 1031)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    mod lookup_names {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let names = vec![
+                "照れ顔".to_string(),
+                "あっち目".to_string(),
+                "前手".to_string(),
+            ];
+
+            let result = lookup_names(&root, &BuildOptions::new(), &names).unwrap();
+            assert_eq!(
+                result
+                    .iter()
+                    .map(|v| (v.character(), v.number()))
+                    .collect::<Vec<(usize, usize)>>(),
+                vec![(0, 221)]
+            );
+
+            let result = lookup_names(&root, &BuildOptions::new(), &names[..2]).unwrap();
+            assert!(result.is_empty());
+        }
+    }
+}
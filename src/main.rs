@@ -1,8 +1,10 @@
 use clap::Parser;
 
-mod ast;
+mod command;
 mod config;
-mod process;
+mod output;
+mod settings;
+mod watch;
 
 fn main() {
     let config = config::Config::parse();
@@ -0,0 +1,171 @@
+use crate::{
+    charset::Charset,
+    constants::{ConstantFormat, ConstantTemplate},
+    error::ProcessError,
+    filter::NameFilter,
+    line_ending::LineEnding,
+    lock::SurfaceLock,
+    whitelist::Whitelist,
+};
+
+const DEFAULT_SEPARATOR: &str = "-";
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Options for building surfacetable.txt and surfaces.txt.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    whitelist: Option<Whitelist>,
+    name_whitelist: Vec<NameFilter>,
+    name_blacklist: Vec<NameFilter>,
+    separator: String,
+    lock: Option<SurfaceLock>,
+    charset: Charset,
+    line_ending: LineEnding,
+    bom: bool,
+    constant_format: ConstantFormat,
+    constant_template: ConstantTemplate,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            whitelist: None,
+            name_whitelist: Vec::new(),
+            name_blacklist: Vec::new(),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            lock: None,
+            charset: Charset::default(),
+            line_ending: LineEnding::default(),
+            bom: false,
+            constant_format: ConstantFormat::default(),
+            constant_template: ConstantTemplate::default(),
+        }
+    }
+}
+
+impl BuildOptions {
+    /// Creates options with default values.
+    pub fn new() -> BuildOptions {
+        Self::default()
+    }
+
+    /// Sets the surface numbers to output. All surfaces are output if not set.
+    pub fn with_whitelist(mut self, whitelist: impl Into<Whitelist>) -> BuildOptions {
+        self.whitelist = Some(whitelist.into());
+        self
+    }
+
+    /// Sets the filters of surfaces to output. Surfaces matching any of them are output.
+    pub fn with_name_whitelist(mut self, filters: Vec<NameFilter>) -> BuildOptions {
+        self.name_whitelist = filters;
+        self
+    }
+
+    /// Sets the filters of surfaces not to output.
+    pub fn with_name_blacklist(mut self, filters: Vec<NameFilter>) -> BuildOptions {
+        self.name_blacklist = filters;
+        self
+    }
+
+    /// Sets the separator string for a parts of the surface.
+    pub fn with_separator(mut self, separator: &str) -> BuildOptions {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Sets the surface numbers assigned in a previous build.
+    pub fn with_lock(mut self, lock: SurfaceLock) -> BuildOptions {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Sets the character encoding of the output.
+    pub fn with_charset(mut self, charset: Charset) -> BuildOptions {
+        self.charset = charset;
+        self
+    }
+
+    /// Sets the line ending of the output.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> BuildOptions {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Sets whether to write a BOM at the beginning of the output. Only used for UTF-8.
+    pub fn with_bom(mut self, bom: bool) -> BuildOptions {
+        self.bom = bom;
+        self
+    }
+
+    /// Sets the format of the constants file.
+    pub fn with_constant_format(mut self, format: ConstantFormat) -> BuildOptions {
+        self.constant_format = format;
+        self
+    }
+
+    /// Sets the template of constant names.
+    pub fn with_constant_template(mut self, template: ConstantTemplate) -> BuildOptions {
+        self.constant_template = template;
+        self
+    }
+
+    /// Surface numbers to output.
+    pub fn whitelist(&self) -> Option<&Whitelist> {
+        self.whitelist.as_ref()
+    }
+
+    /// Filters of surfaces to output.
+    pub fn name_whitelist(&self) -> &Vec<NameFilter> {
+        &self.name_whitelist
+    }
+
+    /// Filters of surfaces not to output.
+    pub fn name_blacklist(&self) -> &Vec<NameFilter> {
+        &self.name_blacklist
+    }
+
+    /// Separator string for a parts of the surface.
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// Surface numbers assigned in a previous build.
+    pub fn lock(&self) -> Option<&SurfaceLock> {
+        self.lock.as_ref()
+    }
+
+    /// Character encoding of the output.
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// Line ending of the output.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Whether to write a BOM at the beginning of the output.
+    pub fn bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Format of the constants file.
+    pub fn constant_format(&self) -> ConstantFormat {
+        self.constant_format
+    }
+
+    /// Template of constant names.
+    pub fn constant_template(&self) -> &ConstantTemplate {
+        &self.constant_template
+    }
+
+    /// Converts the built contents into the bytes to write.
+    pub(crate) fn encode(&self, contents: &str) -> Result<Vec<u8>, ProcessError> {
+        let mut bytes = Vec::new();
+        if self.bom && self.charset == Charset::Utf8 {
+            bytes.extend_from_slice(UTF8_BOM);
+        }
+        bytes.extend(self.charset.encode(&self.line_ending.apply(contents))?);
+        Ok(bytes)
+    }
+}
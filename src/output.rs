@@ -0,0 +1,386 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufWriter, StdoutLock, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use surfacetable_mixer::ProcessError;
+
+const BACKUP_EXTENSION: &str = "bak";
+const TEMPORARY_EXTENSION: &str = "tmp";
+
+/// How to keep the previous version of an output file.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BackupMode {
+    /// Copy to `*.bak`, replacing the older backup.
+    Simple,
+    /// Copy to `*.YYYYMMDDhhmmss.bak`, keeping the newest ones.
+    Timestamped,
+}
+
+/// Settings of backups made before replacing output files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Backup {
+    pub mode: BackupMode,
+    pub keep: usize,
+}
+
+/// Destination of generated contents.
+pub(crate) enum Output<'a> {
+    Stdout(StdoutLock<'static>),
+    File(AtomicFile<'a>),
+}
+
+impl Output<'_> {
+    /// Flushes the contents, and replaces the target file if any.
+    pub(crate) fn commit(self) -> Result<(), ProcessError> {
+        match self {
+            Output::Stdout(mut v) => Ok(v.flush()?),
+            Output::File(v) => v.commit(),
+        }
+    }
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(v) => v.write(buf),
+            Output::File(v) => v.writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(v) => v.flush(),
+            Output::File(v) => v.writer.flush(),
+        }
+    }
+}
+
+/// File written to a temporary path, and renamed to the target on [`AtomicFile::commit`].
+///
+/// The target is left untouched if the writing fails.
+pub(crate) struct AtomicFile<'a> {
+    path: &'a Path,
+    temporary_path: PathBuf,
+    writer: BufWriter<File>,
+    backup: Option<Backup>,
+    committed: bool,
+}
+
+impl<'a> AtomicFile<'a> {
+    pub(crate) fn create(path: &'a Path, backup: Option<Backup>) -> Result<Self, ProcessError> {
+        let temporary_path = sibling_path(
+            path,
+            &format!("{}.{}", std::process::id(), TEMPORARY_EXTENSION),
+        );
+        let file =
+            File::create(&temporary_path).map_err(|e| ProcessError::File(path.to_path_buf(), e))?;
+
+        Ok(AtomicFile {
+            path,
+            temporary_path,
+            writer: BufWriter::new(file),
+            backup,
+            committed: false,
+        })
+    }
+
+    fn commit(mut self) -> Result<(), ProcessError> {
+        let file_error = |path: &Path, e| ProcessError::File(path.to_path_buf(), e);
+
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_all())
+            .map_err(|e| file_error(&self.temporary_path, e))?;
+        if let Ok(metadata) = std::fs::metadata(self.path) {
+            std::fs::set_permissions(&self.temporary_path, metadata.permissions())
+                .map_err(|e| file_error(&self.temporary_path, e))?;
+        }
+
+        if let Some(backup) = self.backup {
+            if self.path.exists() {
+                back_up(self.path, backup)?;
+            }
+        }
+
+        std::fs::rename(&self.temporary_path, self.path).map_err(|e| file_error(self.path, e))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temporary_path);
+        }
+    }
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn back_up(path: &Path, backup: Backup) -> Result<(), ProcessError> {
+    let backup_path = match backup.mode {
+        BackupMode::Simple => sibling_path(path, BACKUP_EXTENSION),
+        BackupMode::Timestamped => {
+            // Backups made in the same second are numbered as `*.YYYYMMDDhhmmss-1.bak`.
+            let timestamp = timestamp();
+            let mut backup_path =
+                sibling_path(path, &format!("{}.{}", timestamp, BACKUP_EXTENSION));
+            let mut count = 0;
+            while backup_path.exists() {
+                count += 1;
+                backup_path = sibling_path(
+                    path,
+                    &format!("{}-{}.{}", timestamp, count, BACKUP_EXTENSION),
+                );
+            }
+            backup_path
+        }
+    };
+
+    std::fs::copy(path, &backup_path).map_err(|e| ProcessError::File(backup_path.clone(), e))?;
+    eprintln!("backed up to {}.", backup_path.display());
+
+    if backup.mode == BackupMode::Timestamped {
+        remove_old_backups(path, backup.keep)?;
+    }
+
+    Ok(())
+}
+
+fn remove_old_backups(path: &Path, keep: usize) -> Result<(), ProcessError> {
+    let name = match path.file_name().and_then(|v| v.to_str()) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let directory = match path.parent() {
+        Some(v) if !v.as_os_str().is_empty() => v,
+        _ => Path::new("."),
+    };
+
+    let mut backups = Vec::new();
+    for entry in
+        std::fs::read_dir(directory).map_err(|e| ProcessError::File(directory.to_path_buf(), e))?
+    {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let key = file_name
+            .to_str()
+            .and_then(|v| v.strip_prefix(name))
+            .and_then(|v| v.strip_prefix('.'))
+            .and_then(|v| v.strip_suffix(BACKUP_EXTENSION))
+            .and_then(|v| v.strip_suffix('.'))
+            .and_then(backup_key);
+        if let Some(key) = key {
+            backups.push((key, entry.path()));
+        }
+    }
+
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for (_, old) in backups.into_iter().take(excess) {
+        std::fs::remove_file(&old).map_err(|e| ProcessError::File(old.clone(), e))?;
+    }
+
+    Ok(())
+}
+
+/// Order of a timestamped backup from `YYYYMMDDhhmmss` or `YYYYMMDDhhmmss-N`.
+fn backup_key(s: &str) -> Option<(u64, usize)> {
+    let (timestamp, count) = match s.split_once('-') {
+        Some((timestamp, count)) if count.bytes().all(|c| c.is_ascii_digit()) => {
+            (timestamp, count.parse().ok()?)
+        }
+        Some(_) => return None,
+        None => (s, 0),
+    };
+
+    if timestamp.len() != 14 || !timestamp.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((timestamp.parse().ok()?, count))
+}
+
+/// Current UTC time in `YYYYMMDDhhmmss`.
+fn timestamp() -> String {
+    format_timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|v| v.as_secs())
+            .unwrap_or_default(),
+    )
+}
+
+/// Seconds since 1970-01-01 in `YYYYMMDDhhmmss`.
+fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Converts days since 1970-01-01 into the civil date.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "surfacetable-mixer-output-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|v| v.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    mod atomic_file {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let directory = temporary_directory("commit");
+            let path = directory.join("surfacetable.txt");
+            std::fs::write(&path, "old").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+            }
+
+            let mut output = Output::File(AtomicFile::create(&path, None).unwrap());
+            output.write_all(b"new").unwrap();
+            output.commit().unwrap();
+
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+            assert_eq!(file_names(&directory), vec!["surfacetable.txt"]);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o640);
+            }
+        }
+
+        #[test]
+        fn checking_value_when_dropped() {
+            let directory = temporary_directory("drop");
+            let path = directory.join("surfacetable.txt");
+            std::fs::write(&path, "old").unwrap();
+
+            let mut output = Output::File(AtomicFile::create(&path, None).unwrap());
+            output.write_all(b"new").unwrap();
+            assert_eq!(file_names(&directory).len(), 2);
+            drop(output);
+
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+            assert_eq!(file_names(&directory), vec!["surfacetable.txt"]);
+        }
+    }
+
+    mod back_up {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let directory = temporary_directory("back-up");
+            let path = directory.join("surfacetable.txt");
+            std::fs::write(&path, "old").unwrap();
+            let backup = Backup {
+                mode: BackupMode::Timestamped,
+                keep: 5,
+            };
+
+            back_up(&path, backup).unwrap();
+            back_up(&path, backup).unwrap();
+
+            let backups: Vec<String> = file_names(&directory)
+                .into_iter()
+                .filter(|v| v.ends_with(".bak"))
+                .collect();
+            assert_eq!(backups.len(), 2);
+        }
+    }
+
+    mod remove_old_backups {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let directory = temporary_directory("remove");
+            for name in [
+                "surfacetable.txt",
+                "surfacetable.txt.bak",
+                "surfacetable.txt.20260101000002.bak",
+                "surfacetable.txt.20260101000001.bak",
+                "surfacetable.txt.20260101000002-2.bak",
+                "surfacetable.txt.20260101000002-10.bak",
+                "surfaces.txt.20260101000001.bak",
+            ] {
+                std::fs::write(directory.join(name), "").unwrap();
+            }
+
+            remove_old_backups(&directory.join("surfacetable.txt"), 2).unwrap();
+            assert_eq!(
+                file_names(&directory),
+                vec![
+                    "surfaces.txt.20260101000001.bak",
+                    "surfacetable.txt",
+                    "surfacetable.txt.20260101000002-10.bak",
+                    "surfacetable.txt.20260101000002-2.bak",
+                    "surfacetable.txt.bak",
+                ]
+            );
+        }
+    }
+
+    mod format_timestamp {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            assert_eq!(format_timestamp(0), "19700101000000");
+            assert_eq!(format_timestamp(951_782_400), "20000229000000");
+            assert_eq!(format_timestamp(1_767_225_599), "20251231235959");
+            assert_eq!(format_timestamp(4_107_542_400), "21000301000000");
+        }
+    }
+}
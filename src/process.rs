@@ -1,175 +1,88 @@
-use std::{
-    fmt::Display,
-    fs::File,
-    io::{BufRead, Read, Write},
-    path::PathBuf,
-};
+use std::{fs::File, io::Read, io::Write, path::Path};
 
 use crate::{
-    ast::{Character, GroupData, Root},
-    config::Config,
+    ast::{Character, GroupData, PoseData, Root},
+    error::ProcessError,
+    filter::{filter_surfaces_by_names, validate_filters},
+    input_format::InputFormat,
+    locate::Segment,
+    lock::SurfaceLock,
+    options::BuildOptions,
+    rules::{allows_surface, validate_rules},
+    whitelist::Whitelist,
 };
 
 #[derive(Debug, PartialEq, Clone)]
-struct SurfacePart {
+pub(crate) struct SurfacePart {
     pub number: usize,
     pub digits: usize,
 }
 
-type SurfaceNumber = Vec<SurfacePart>;
+pub(crate) type SurfaceNumber = Vec<SurfacePart>;
+pub(crate) type CharacterSurfaces = Vec<(usize, SurfaceNumber)>;
 type SurfacePose = Vec<SurfaceNumber>;
 // type SurfaceGroup = Vec<SurfacePose>;
 
-#[derive(Debug)]
-pub(crate) enum ProcessError {
-    Io(std::io::Error),
-    Serde(serde_yml::Error),
+/// Reads a YAML file into [`Root`].
+pub fn read_yaml(path: &Path) -> Result<Root, ProcessError> {
+    InputFormat::Yaml.read(path)
 }
 
-impl From<std::io::Error> for ProcessError {
-    fn from(value: std::io::Error) -> Self {
-        Self::Io(value)
-    }
-}
-
-impl From<serde_yml::Error> for ProcessError {
-    fn from(value: serde_yml::Error) -> Self {
-        Self::Serde(value)
-    }
-}
-
-impl Display for ProcessError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Io(e) => write!(f, "{}", e),
-            Self::Serde(e) => write!(f, "{}", e),
-        }
-    }
+/// Parses a YAML string into [`Root`].
+///
+/// Included files are relative to the current directory.
+pub fn parse_yaml(s: &str) -> Result<Root, ProcessError> {
+    InputFormat::Yaml.parse(s)
 }
 
-pub(crate) fn process(config: &Config) -> Result<(), ProcessError> {
-    if config.output().exists() && !config.force() {
-        confirm_overwriting()?;
-    }
-
-    let root = read_yaml(config.input())?;
-
-    let contents = build_surfacetable(&root, config.whitelist(), config.separator());
-
-    write_surfacetable(config.output(), &contents)?;
-
-    println!("saved to {}.", config.output().display());
-
-    Ok(())
-}
+pub(crate) fn read_file(path: &Path) -> Result<String, ProcessError> {
+    let file_error = |e| ProcessError::File(path.to_path_buf(), e);
 
-fn confirm_overwriting() -> Result<(), ProcessError> {
-    let stdin = std::io::stdin();
-    let mut buf_reader = std::io::BufReader::new(stdin);
-
-    let stdout = std::io::stdout();
-    let stdout_lock = stdout.lock();
-    let mut buf_writer = std::io::BufWriter::new(stdout_lock);
-
-    let mut s = String::new();
-    buf_writer.write_all(b"The output file already exists.\n")?;
-
-    loop {
-        buf_writer.write_all(b"Do you want to overwrite the file? [Y/n]: ")?;
-        buf_writer.flush()?;
-
-        s.clear();
-        buf_reader.read_line(&mut s)?;
-
-        match s.trim().to_ascii_lowercase().as_str() {
-            "y" | "yes" => {
-                buf_writer.write_all(b"The file will be overwritten.\n")?;
-                buf_writer.flush()?;
-                break;
-            }
-            "n" | "no" => {
-                buf_writer.write_all(b"Closing this program...\n")?;
-                buf_writer.flush()?;
-                std::process::exit(0);
-            }
-            _ => {
-                buf_writer.write_all(
-                    b"Please input 'Y' or 'n' (To exit from this program, input 'n').\n",
-                )?;
-            }
-        }
-    }
-
-    Ok(())
-}
-
-fn read_yaml(path: &PathBuf) -> Result<Root, ProcessError> {
-    let mut fs = File::open(path)?;
+    let mut fs = File::open(path).map_err(file_error)?;
     let mut buffer = String::new();
-    fs.read_to_string(&mut buffer)?;
+    fs.read_to_string(&mut buffer).map_err(file_error)?;
 
-    serde_yml::from_str::<Root>(&buffer).map_err(|e| e.into())
+    Ok(buffer)
 }
 
-fn write_surfacetable(path: &PathBuf, contents: &str) -> Result<(), ProcessError> {
-    let mut fs = File::create(path)?;
-    fs.write_all(contents.as_bytes())?;
+/// Writes the surfacetable built from `root` to `writer`.
+pub fn write_surfacetable<W: Write>(
+    root: &Root,
+    options: &BuildOptions,
+    writer: &mut W,
+) -> Result<(), ProcessError> {
+    let contents = build_surfacetable(root, options)?;
+    writer.write_all(&options.encode(&contents)?)?;
     Ok(())
 }
 
-fn build_surfacetable(root: &Root, whitelist: Option<&Vec<usize>>, separator: &str) -> String {
-    let offset_origin = generate_surface_offset(root.characters());
-    let mut contents = r#"charset,UTF-8
-version,1
-"#
-    .to_string();
+/// Builds the contents of surfacetable.txt from `root`.
+pub fn build_surfacetable(root: &Root, options: &BuildOptions) -> Result<String, ProcessError> {
+    let separator = options.separator();
+    let surfaces = enumerate_surfaces(root, options)?;
+    let mut contents = format!("charset,{}\nversion,1\n", options.charset().name());
 
     for (index, character) in root.characters().iter().enumerate() {
-        let tables =
-            build_surfacetable_by_character(character, index, offset_origin, whitelist, separator);
+        let tables = build_surfacetable_by_character(character, index, &surfaces[index], separator);
         contents.push_str(&tables);
     }
 
-    contents
+    Ok(contents)
 }
 
 fn build_surfacetable_by_character(
     character: &Character,
     character_index: usize,
-    offset: usize,
-    whitelist: Option<&Vec<usize>>,
+    surfaces: &CharacterSurfaces,
     separator: &str,
 ) -> String {
-    let surfaces = generate_surfaces(character.parts());
-
     let mut contents = String::new();
 
-    let mut category = String::new();
-    for surface_number in surfaces.iter() {
-        category.clear();
-        let surface_number_base = combine_number(surface_number);
-        let surface_number_result = (character_index * offset) + surface_number_base;
-        if let Some(list) = whitelist {
-            if !list.iter().any(|v| v == &surface_number_result) {
-                continue;
-            }
-        }
-
-        for (index_parts, parts) in surface_number.iter().enumerate() {
-            if let Some(v) = character
-                .parts()
-                .get(index_parts)
-                .and_then(|group_data| group_data.details().get(parts.number - 1))
-            {
-                category.push_str(v.name());
-                category.push_str(separator);
-            }
-        }
-
+    for (surface_number_result, surface_number) in surfaces.iter() {
         contents.push_str(&format!(
             "{},{}\n",
             surface_number_result,
-            category.strip_suffix(separator).unwrap_or(&category)
+            surface_names(character, surface_number).join(separator)
         ));
     }
 
@@ -183,27 +96,203 @@ fn build_surfacetable_by_character(
     }
 }
 
-fn generate_surface_offset(characters: &[Character]) -> usize {
+/// Groups and their poses which make up the surface, skipping absent ones.
+pub(crate) fn surface_pose_data<'a>(
+    character: &'a Character,
+    surface_number: &SurfaceNumber,
+) -> Vec<(&'a GroupData, &'a PoseData)> {
+    surface_number
+        .iter()
+        .zip(character.parts().iter())
+        .filter_map(|(part, group_data)| {
+            group_data
+                .details()
+                .get(part.number - 1)
+                .map(|pose_data| (group_data, pose_data))
+        })
+        .collect()
+}
+
+pub(crate) fn surface_names<'a>(
+    character: &'a Character,
+    surface_number: &SurfaceNumber,
+) -> Vec<&'a str> {
+    surface_pose_data(character, surface_number)
+        .into_iter()
+        .map(|(_, pose_data)| pose_data.name().as_str())
+        .collect()
+}
+
+pub(crate) fn enumerate_surfaces(
+    root: &Root,
+    options: &BuildOptions,
+) -> Result<Vec<CharacterSurfaces>, ProcessError> {
+    let surfaces = number_surfaces(root, options.lock())?;
+    validate_filters(root, options)?;
+
+    Ok(root
+        .characters()
+        .iter()
+        .zip(surfaces)
+        .enumerate()
+        .map(|(index, (character, v))| {
+            let v = filter_surfaces(v, index, options.whitelist());
+            filter_surfaces_by_names(character, v, options)
+        })
+        .collect())
+}
+
+pub(crate) fn number_surfaces(
+    root: &Root,
+    lock: Option<&SurfaceLock>,
+) -> Result<Vec<CharacterSurfaces>, ProcessError> {
+    let offsets = generate_character_offsets(root)?;
+    for (index, character) in root.characters().iter().enumerate() {
+        validate_rules(character, index)?;
+    }
+
+    let mut surfaces: Vec<CharacterSurfaces> = root
+        .characters()
+        .iter()
+        .enumerate()
+        .map(|(index, character)| enumerate_character_surfaces(character, offsets[index]))
+        .collect();
+
+    if let Some(lock) = lock {
+        lock.assign(root, &offsets, &mut surfaces)?;
+    }
+
+    Ok(surfaces)
+}
+
+pub(crate) fn enumerate_character_surfaces(
+    character: &Character,
+    offset: usize,
+) -> CharacterSurfaces {
+    generate_surfaces(character.parts())
+        .into_iter()
+        .filter(|surface_number| allows_surface(character, surface_number))
+        .map(|surface_number| (offset + combine_number(&surface_number), surface_number))
+        .collect()
+}
+
+pub(crate) fn filter_surfaces(
+    surfaces: CharacterSurfaces,
+    character_index: usize,
+    whitelist: Option<&Whitelist>,
+) -> CharacterSurfaces {
+    match whitelist {
+        Some(list) => surfaces
+            .into_iter()
+            .filter(|(number, _)| list.contains(character_index, *number))
+            .collect(),
+        None => surfaces,
+    }
+}
+
+pub(crate) fn generate_character_offsets(root: &Root) -> Result<Vec<usize>, ProcessError> {
+    let step = match root.offset() {
+        Some(v) => v,
+        None => generate_surface_offset(root.characters())?,
+    };
+
+    let mut offsets = Vec::new();
+    let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+    for (index, character) in root.characters().iter().enumerate() {
+        let overflow =
+            || ProcessError::Overflow { character: index }.in_source(character.origin(), &[]);
+
+        let offset = match character.offset() {
+            Some(v) => v,
+            None => index.checked_mul(step).ok_or_else(overflow)?,
+        };
+        offsets.push(offset);
+
+        let (first, last) = match surface_range(character, index)? {
+            Some(v) => v,
+            None => continue,
+        };
+        let range = (
+            index,
+            offset.checked_add(first).ok_or_else(overflow)?,
+            offset.checked_add(last).ok_or_else(overflow)?,
+        );
+
+        if let Some(other) = ranges
+            .iter()
+            .find(|other| range.1 <= other.2 && other.1 <= range.2)
+        {
+            return Err(ProcessError::OffsetOverlap {
+                characters: (other.0, range.0),
+                ranges: ((other.1, other.2), (range.1, range.2)),
+            }
+            .in_source(character.origin(), &[Segment::Key("offset")]));
+        }
+        ranges.push(range);
+    }
+
+    Ok(offsets)
+}
+
+pub(crate) fn generate_surface_offset(characters: &[Character]) -> Result<usize, ProcessError> {
     let mut max_in_all = 0;
+    let mut index_max = 0;
 
-    for c in characters {
-        let surfaces = generate_surfaces(c.parts());
-        let max = match surfaces.last() {
-            Some(v) => combine_number(v),
+    for (index, c) in characters.iter().enumerate() {
+        let max = match surface_range(c, index)? {
+            Some((_, v)) => v,
             None => continue,
         };
         if max > max_in_all {
             max_in_all = max;
+            index_max = index;
         }
     }
 
-    let mut offset = 1;
     let digits = count_digits(max_in_all);
-    for _index in 0..digits {
-        offset *= 10;
+    10_usize.checked_pow(digits as u32).ok_or_else(|| {
+        ProcessError::Overflow {
+            character: index_max,
+        }
+        .in_source(characters[index_max].origin(), &[])
+    })
+}
+
+/// Range of the surface numbers of `character` without the offset.
+/// `None` if some groups have no poses.
+pub(crate) fn surface_range(
+    character: &Character,
+    character_index: usize,
+) -> Result<Option<(usize, usize)>, ProcessError> {
+    let overflow = || {
+        ProcessError::Overflow {
+            character: character_index,
+        }
+        .in_source(character.origin(), &[])
+    };
+
+    let mut first: usize = 0;
+    let mut last: usize = 0;
+    let mut next_digits = Some(1_usize);
+    for group_data in character.parts().iter().rev() {
+        let len = group_data.details().len();
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let digits = next_digits.ok_or_else(overflow)?;
+        first = first.checked_add(digits).ok_or_else(overflow)?;
+        last = digits
+            .checked_mul(len)
+            .and_then(|v| last.checked_add(v))
+            .ok_or_else(overflow)?;
+
+        next_digits = 10_usize
+            .checked_pow(count_digits(len) as u32)
+            .and_then(|v| digits.checked_mul(v));
     }
 
-    offset
+    Ok(Some((first, last)))
 }
 
 fn generate_surfaces(parts: &[GroupData]) -> SurfacePose {
@@ -331,7 +420,8 @@ mod tests {
                 ],
             );
             let separator = "-";
-            let result = build_surfacetable(&root, None, &separator);
+            let result =
+                build_surfacetable(&root, &BuildOptions::new().with_separator(separator)).unwrap();
             assert_eq!(
                 result,
                 r#"charset,UTF-8
@@ -363,7 +453,13 @@ scope,1
             );
 
             let whitelist = vec![11];
-            let result = build_surfacetable(&root, Some(&whitelist), &separator);
+            let result = build_surfacetable(
+                &root,
+                &BuildOptions::new()
+                    .with_whitelist(whitelist)
+                    .with_separator(separator),
+            )
+            .unwrap();
             assert_eq!(
                 result,
                 r#"charset,UTF-8
@@ -434,16 +530,20 @@ scope,0
                     ],
                 ),
             ];
-            let offset = generate_surface_offset(&characters);
+            let offset = generate_surface_offset(&characters).unwrap();
             let character_index = 0;
             let separator = "-";
 
+            let surfaces = enumerate_character_surfaces(
+                &characters[character_index],
+                character_index * offset,
+            );
+
             let result = build_surfacetable_by_character(
                 &characters[character_index],
                 character_index,
-                offset,
-                None,
-                &separator,
+                &surfaces,
+                separator,
             );
 
             assert_eq!(
@@ -461,16 +561,24 @@ scope,0
                 .to_string()
             );
 
-            let offset = generate_surface_offset(&characters);
+            let offset = generate_surface_offset(&characters).unwrap();
             let character_index = 1;
-            let whitelist = vec![1111, 1211, 1222];
+            let whitelist = Whitelist::from(vec![1111, 1211, 1222]);
+
+            let surfaces = filter_surfaces(
+                enumerate_character_surfaces(
+                    &characters[character_index],
+                    character_index * offset,
+                ),
+                character_index,
+                Some(&whitelist),
+            );
 
             let result = build_surfacetable_by_character(
                 &characters[character_index],
                 character_index,
-                offset,
-                Some(&whitelist),
-                &separator,
+                &surfaces,
+                separator,
             );
 
             assert_eq!(
@@ -489,6 +597,71 @@ scope,1
         }
     }
 
+    mod generate_character_offsets {
+        use super::*;
+
+        use crate::ast::PoseData;
+
+        fn case_character(groups: usize) -> Character {
+            Character::new(
+                None,
+                (0..groups)
+                    .map(|index| {
+                        GroupData::new(
+                            format!("testgroup_{:02}", index),
+                            vec![
+                                PoseData::new("testpose_01".to_string(), "element0...".to_string()),
+                                PoseData::new("testpose_02".to_string(), "element0...".to_string()),
+                            ],
+                        )
+                    })
+                    .collect(),
+            )
+        }
+
+        #[test]
+        fn checking_value() {
+            let case = Root::new(None, vec![case_character(2), case_character(3)]);
+            let result = generate_character_offsets(&case).unwrap();
+            assert_eq!(result, vec![0, 1000]);
+
+            let case =
+                Root::new(None, vec![case_character(2), case_character(3)]).with_offset(5000);
+            let result = generate_character_offsets(&case).unwrap();
+            assert_eq!(result, vec![0, 5000]);
+
+            let case = Root::new(
+                None,
+                vec![
+                    case_character(2).with_offset(1000),
+                    case_character(3),
+                    case_character(2),
+                ],
+            )
+            .with_offset(10000);
+            let result = generate_character_offsets(&case).unwrap();
+            assert_eq!(result, vec![1000, 10000, 20000]);
+        }
+
+        #[test]
+        fn failed_when_ranges_overlap() {
+            let case = Root::new(
+                None,
+                vec![case_character(3), case_character(2).with_offset(100)],
+            );
+            match generate_character_offsets(&case) {
+                Err(ProcessError::OffsetOverlap { characters, ranges }) => {
+                    assert_eq!(characters, (0, 1));
+                    assert_eq!(ranges, ((111, 222), (111, 122)));
+                }
+                _ => panic!("expected overlap"),
+            }
+
+            let case = Root::new(None, vec![case_character(2), case_character(2)]).with_offset(10);
+            assert!(generate_character_offsets(&case).is_err());
+        }
+    }
+
     mod generate_surface_offset {
         use super::*;
 
@@ -543,7 +716,7 @@ scope,1
                     ],
                 ),
             ];
-            let result = generate_surface_offset(&case);
+            let result = generate_surface_offset(&case).unwrap();
             assert_eq!(result, 1000);
         }
     }
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::Character,
+    error::ProcessError,
+    locate::Segment,
+    process::{surface_pose_data, SurfaceNumber},
+};
+
+const GROUP_SEPARATOR: char = '=';
+
+/// Constraint on combinations of poses.
+///
+/// Poses are referred by `pose` or `group=pose`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pose: String,
+    #[serde(default)]
+    excludes: Vec<String>,
+    #[serde(default)]
+    requires: Vec<String>,
+}
+
+impl Rule {
+    /// Creates a new rule.
+    pub fn new(pose: String, excludes: Vec<String>, requires: Vec<String>) -> Rule {
+        Rule {
+            pose,
+            excludes,
+            requires,
+        }
+    }
+
+    /// Pose which the rule applies to.
+    pub fn pose(&self) -> &String {
+        &self.pose
+    }
+
+    /// Poses which cannot be combined with [`Rule::pose`].
+    pub fn excludes(&self) -> &Vec<String> {
+        &self.excludes
+    }
+
+    /// Poses which must be combined with [`Rule::pose`].
+    /// If some poses of the same group are listed, one of them is required.
+    pub fn requires(&self) -> &Vec<String> {
+        &self.requires
+    }
+
+    fn references(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.pose)
+            .chain(self.excludes.iter())
+            .chain(self.requires.iter())
+    }
+
+    fn allows(&self, poses: &[(&str, &str)]) -> bool {
+        if !poses.iter().any(|pose| matches_pose(&self.pose, pose)) {
+            return true;
+        }
+
+        if self
+            .excludes
+            .iter()
+            .any(|reference| poses.iter().any(|pose| matches_pose(reference, pose)))
+        {
+            return false;
+        }
+
+        let mut required_groups: Vec<Option<&str>> = Vec::new();
+        for reference in self.requires.iter() {
+            let group = split_reference(reference).0;
+            if !required_groups.contains(&group) {
+                required_groups.push(group);
+            }
+        }
+
+        required_groups.iter().all(|group| {
+            self.requires
+                .iter()
+                .filter(|reference| split_reference(reference).0 == *group)
+                .any(|reference| poses.iter().any(|pose| matches_pose(reference, pose)))
+        })
+    }
+}
+
+fn split_reference(reference: &str) -> (Option<&str>, &str) {
+    match reference.split_once(GROUP_SEPARATOR) {
+        Some((group, pose)) => (Some(group), pose),
+        None => (None, reference),
+    }
+}
+
+pub(crate) fn matches_pose(reference: &str, (group, pose): &(&str, &str)) -> bool {
+    match split_reference(reference) {
+        (Some(g), p) => g == *group && p == *pose,
+        (None, p) => p == *pose,
+    }
+}
+
+pub(crate) fn surface_poses<'a>(
+    character: &'a Character,
+    surface_number: &SurfaceNumber,
+) -> Vec<(&'a str, &'a str)> {
+    surface_pose_data(character, surface_number)
+        .into_iter()
+        .map(|(group_data, pose_data)| (group_data.group().as_str(), pose_data.name().as_str()))
+        .collect()
+}
+
+pub(crate) fn allows_surface(character: &Character, surface_number: &SurfaceNumber) -> bool {
+    if character.rules().is_empty() {
+        return true;
+    }
+
+    let poses = surface_poses(character, surface_number);
+    character.rules().iter().all(|rule| rule.allows(&poses))
+}
+
+pub(crate) fn validate_rules(
+    character: &Character,
+    character_index: usize,
+) -> Result<(), ProcessError> {
+    for (index, reference) in character
+        .rules()
+        .iter()
+        .enumerate()
+        .flat_map(|(index, rule)| rule.references().map(move |v| (index, v)))
+    {
+        let exists = character.parts().iter().any(|group_data| {
+            group_data.details().iter().any(|pose_data| {
+                matches_pose(
+                    reference,
+                    &(group_data.group().as_str(), pose_data.name().as_str()),
+                )
+            })
+        });
+        if !exists {
+            return Err(ProcessError::UnknownPose {
+                character: character_index,
+                reference: reference.to_string(),
+            }
+            .in_source(
+                character.origin(),
+                &[
+                    Segment::Key("rules"),
+                    Segment::Index(index),
+                    Segment::Text(reference),
+                ],
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ast::{GroupData, PoseData, Root},
+        options::BuildOptions,
+        process::build_surfacetable,
+    };
+
+    fn case_character(rules: Vec<Rule>) -> Character {
+        Character::new(
+            None,
+            vec![
+                GroupData::new(
+                    "face".to_string(),
+                    vec![
+                        PoseData::new("normal".to_string(), "element0...".to_string()),
+                        PoseData::new("shy".to_string(), "element0...".to_string()),
+                    ],
+                ),
+                GroupData::new(
+                    "eyes".to_string(),
+                    vec![
+                        PoseData::new("front".to_string(), "element0...".to_string()),
+                        PoseData::new("side".to_string(), "element0...".to_string()),
+                        PoseData::new("closed".to_string(), "element0...".to_string()),
+                    ],
+                ),
+            ],
+        )
+        .with_rules(rules)
+    }
+
+    mod allows {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let rule = Rule::new("closed".to_string(), vec!["shy".to_string()], vec![]);
+            assert!(!rule.allows(&[("face", "shy"), ("eyes", "closed")]));
+            assert!(rule.allows(&[("face", "normal"), ("eyes", "closed")]));
+            assert!(rule.allows(&[("face", "shy"), ("eyes", "side")]));
+
+            let rule = Rule::new(
+                "face=shy".to_string(),
+                vec![],
+                vec!["eyes=front".to_string(), "eyes=closed".to_string()],
+            );
+            assert!(rule.allows(&[("face", "shy"), ("eyes", "front")]));
+            assert!(rule.allows(&[("face", "shy"), ("eyes", "closed")]));
+            assert!(!rule.allows(&[("face", "shy"), ("eyes", "side")]));
+            assert!(rule.allows(&[("face", "normal"), ("eyes", "side")]));
+        }
+    }
+
+    mod validate_rules {
+        use super::*;
+
+        #[test]
+        fn failed_when_pose_is_unknown() {
+            let case = case_character(vec![Rule::new(
+                "closed".to_string(),
+                vec!["face=side".to_string()],
+                vec![],
+            )]);
+            match validate_rules(&case, 1) {
+                Err(ProcessError::UnknownPose {
+                    character,
+                    reference,
+                }) => {
+                    assert_eq!(character, 1);
+                    assert_eq!(reference, "face=side");
+                }
+                _ => panic!("expected unknown pose"),
+            }
+
+            let case = case_character(vec![Rule::new(
+                "closed".to_string(),
+                vec!["eyes=side".to_string()],
+                vec![],
+            )]);
+            assert!(validate_rules(&case, 0).is_ok());
+        }
+    }
+
+    mod build_surfacetable_with_rules {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let case = r#"
+characters:
+  - parts:
+    - group: face
+      details:
+        - name: normal
+          text: ""
+        - name: shy
+          text: ""
+    - group: eyes
+      details:
+        - name: front
+          text: ""
+        - name: side
+          text: ""
+        - name: closed
+          text: ""
+    rules:
+      - pose: closed
+        excludes: [shy]
+      - pose: side
+        requires: [face=normal]
+"#;
+            let root: Root = serde_yml::from_str(case).unwrap();
+            let result = build_surfacetable(&root, &BuildOptions::new()).unwrap();
+            assert_eq!(
+                result,
+                r#"charset,UTF-8
+version,1
+
+group,\0
+{
+scope,0
+11,normal-front
+12,normal-side
+13,normal-closed
+21,shy-front
+}
+"#
+            );
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{de::Error, Deserialize, Deserializer};
+use surfacetable_mixer::{
+    Charset, ConstantFormat, ConstantTemplate, InputFormat, LineEnding, NameFilter, Whitelist,
+};
+
+use crate::config::OnExists;
+
+/// Name of the project config file looked up in the current directory.
+const DEFAULT_SETTINGS_PATH: &str = "./surfacetable-mixer.toml";
+
+/// Options read from the project config file.
+///
+/// Paths are relative to the directory of the file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Settings {
+    pub input: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub input_format: Option<InputFormat>,
+    pub output: Option<PathBuf>,
+    pub surfaces: Option<PathBuf>,
+    pub constants: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub constants_format: Option<ConstantFormat>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub constants_template: Option<ConstantTemplate>,
+    pub lock: Option<PathBuf>,
+    pub separator: Option<String>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub whitelist: Option<Whitelist>,
+    pub whitelist_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parse_vec")]
+    pub name_whitelist: Vec<NameFilter>,
+    #[serde(default, deserialize_with = "parse_vec")]
+    pub name_blacklist: Vec<NameFilter>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub charset: Option<Charset>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub line_ending: Option<LineEnding>,
+    pub bom: Option<bool>,
+    pub offset: Option<usize>,
+    pub on_exists: Option<OnExists>,
+}
+
+fn parse_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|v| T::from_str(&v).map_err(D::Error::custom))
+        .transpose()
+}
+
+fn parse_vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|v| T::from_str(v).map_err(D::Error::custom))
+        .collect()
+}
+
+/// Error while reading the project config file.
+#[derive(Debug)]
+pub(crate) struct SettingsError {
+    path: PathBuf,
+    message: String,
+}
+
+impl SettingsError {
+    pub(crate) fn new(path: &Path, message: String) -> SettingsError {
+        SettingsError {
+            path: path.to_path_buf(),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message.trim_end())
+    }
+}
+
+impl Settings {
+    /// Reads the file at `path`, or the default one if it exists.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Settings, SettingsError> {
+        let path = match path {
+            Some(v) => v,
+            None if Path::new(DEFAULT_SETTINGS_PATH).exists() => Path::new(DEFAULT_SETTINGS_PATH),
+            None => return Ok(Settings::default()),
+        };
+
+        let buffer =
+            std::fs::read_to_string(path).map_err(|e| SettingsError::new(path, e.to_string()))?;
+        let settings: Settings =
+            toml::from_str(&buffer).map_err(|e| SettingsError::new(path, e.to_string()))?;
+
+        Ok(settings.relative_to(path.parent().unwrap_or(Path::new(""))))
+    }
+
+    fn relative_to(mut self, directory: &Path) -> Settings {
+        let resolve = |path: &mut Option<PathBuf>| {
+            if let Some(v) = path.as_mut() {
+                if v.is_relative() && v.as_os_str() != "-" {
+                    *v = directory.join(&v);
+                }
+            }
+        };
+
+        resolve(&mut self.input);
+        resolve(&mut self.output);
+        resolve(&mut self.surfaces);
+        resolve(&mut self.constants);
+        resolve(&mut self.lock);
+        resolve(&mut self.whitelist_file);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::config::Config;
+
+    mod relative_to {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let absolute = std::env::temp_dir().join("surfaces.lock.yaml");
+            let settings = Settings {
+                input: Some(PathBuf::from("surfaces.yaml")),
+                output: Some(PathBuf::from("-")),
+                surfaces: Some(PathBuf::from("../shell/surfaces.txt")),
+                lock: Some(absolute.clone()),
+                ..Default::default()
+            }
+            .relative_to(Path::new("ghost"));
+
+            assert_eq!(settings.input, Some(PathBuf::from("ghost/surfaces.yaml")));
+            assert_eq!(settings.output, Some(PathBuf::from("-")));
+            assert_eq!(
+                settings.surfaces,
+                Some(PathBuf::from("ghost/../shell/surfaces.txt"))
+            );
+            assert_eq!(settings.lock, Some(absolute));
+            assert_eq!(settings.constants, None);
+        }
+    }
+
+    mod apply_settings {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let directory = std::env::temp_dir().join(format!(
+                "surfacetable-mixer-settings-{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&directory);
+            std::fs::create_dir_all(&directory).unwrap();
+            let path = directory.join("surfacetable-mixer.toml");
+            std::fs::write(
+                &path,
+                "output = \"out.txt\"\nseparator = \"_\"\nbom = true\n",
+            )
+            .unwrap();
+            let path = path.to_str().unwrap();
+
+            let mut config = Config::parse_from(["surfacetable-mixer", "--config", path]);
+            config.apply_settings().unwrap();
+            assert_eq!(config.output(), directory.join("out.txt"));
+            assert_eq!(config.separator(), "_");
+            assert!(config.build_options().unwrap().bom());
+
+            let mut config = Config::parse_from([
+                "surfacetable-mixer",
+                "--config",
+                path,
+                "-o",
+                "cli.txt",
+                "-s",
+                "+",
+                "--bom=false",
+            ]);
+            config.apply_settings().unwrap();
+            assert_eq!(config.output(), Path::new("cli.txt"));
+            assert_eq!(config.separator(), "+");
+            assert!(!config.build_options().unwrap().bom());
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::io::Write;
+
+use crate::{
+    ast::{Character, Root},
+    error::ProcessError,
+    options::BuildOptions,
+    process::{enumerate_surfaces, CharacterSurfaces},
+    template::{expand, has_variables, TemplateContext},
+};
+
+const INDENT: &str = "  ";
+
+/// Writes the surfaces.txt built from `root` to `writer`.
+pub fn write_surfaces<W: Write>(
+    root: &Root,
+    options: &BuildOptions,
+    writer: &mut W,
+) -> Result<(), ProcessError> {
+    let contents = build_surfaces(root, options)?;
+    writer.write_all(&options.encode(&contents)?)?;
+    Ok(())
+}
+
+/// Builds the contents of surfaces.txt from `root`.
+///
+/// Surface numbers are the same as the ones in [`crate::build_surfacetable`].
+pub fn build_surfaces(root: &Root, options: &BuildOptions) -> Result<String, ProcessError> {
+    let surfaces = enumerate_surfaces(root, options)?;
+    let mut sections = vec![format!("charset,{}", options.charset().name())];
+
+    if let Some(raw) = root.raw() {
+        sections.push(raw.trim_end_matches('\n').to_string());
+    }
+
+    let mut characters = Vec::new();
+    for (index, character) in root.characters().iter().enumerate() {
+        let contents = build_surfaces_by_character(character, index, &surfaces[index])?;
+        if !contents.is_empty() {
+            characters.push(contents);
+        }
+    }
+    if !characters.is_empty() {
+        sections.push(characters.join("\n\n\n"));
+    }
+
+    Ok(sections.join("\n\n"))
+}
+
+fn build_surfaces_by_character(
+    character: &Character,
+    character_index: usize,
+    surfaces: &CharacterSurfaces,
+) -> Result<String, ProcessError> {
+    if surfaces.is_empty() {
+        return Ok(String::new());
+    }
+
+    let expand_for = |text: &str, number: usize, context: TemplateContext| {
+        expand(text, &context).map_err(|message| {
+            ProcessError::Template {
+                character: character_index,
+                surface: number,
+                message,
+            }
+            .in_source(character.origin(), &[])
+        })
+    };
+
+    let mut blocks = Vec::new();
+
+    for (index_group, group_data) in character.parts().iter().enumerate() {
+        let mut group_blocks = Vec::new();
+
+        for (index_pose, pose_data) in group_data.details().iter().enumerate() {
+            let matched: Vec<_> = surfaces
+                .iter()
+                .filter(|(_, surface_number)| {
+                    surface_number
+                        .get(index_group)
+                        .is_some_and(|part| part.number == index_pose + 1)
+                })
+                .collect();
+            if matched.is_empty() {
+                continue;
+            }
+
+            // Text with variables differs between surfaces, so each one gets its own block.
+            if has_variables(pose_data.text()) {
+                for (number, surface_number) in matched {
+                    let context =
+                        TemplateContext::new(character_index, character, *number, surface_number)
+                            .with_current(index_group);
+                    let text = expand_for(pose_data.text(), *number, context)?;
+                    group_blocks.push(surface_block(&number.to_string(), pose_data.name(), &text));
+                }
+            } else {
+                let numbers: Vec<String> = matched.iter().map(|(v, _)| v.to_string()).collect();
+                group_blocks.push(surface_block(
+                    &numbers.join(","),
+                    pose_data.name(),
+                    pose_data.text(),
+                ));
+            }
+        }
+
+        if !group_blocks.is_empty() {
+            blocks.push(format!("// {}", group_data.group()));
+            blocks.append(&mut group_blocks);
+        }
+    }
+
+    if let Some(base) = character.base() {
+        if has_variables(base) {
+            for (number, surface_number) in surfaces.iter() {
+                let context =
+                    TemplateContext::new(character_index, character, *number, surface_number);
+                let text = expand_for(base, *number, context)?;
+                blocks.push(format!(
+                    "surface.append{}\n{{\n{}\n}}",
+                    number,
+                    indent_lines(&text)
+                ));
+            }
+        } else {
+            let min = surfaces.iter().map(|(number, _)| *number).min();
+            let max = surfaces.iter().map(|(number, _)| *number).max();
+            if let (Some(min), Some(max)) = (min, max) {
+                blocks.push(format!(
+                    "surface.append{}-{}\n{{\n{}\n}}",
+                    min,
+                    max,
+                    indent_lines(base)
+                ));
+            }
+        }
+    }
+
+    Ok(blocks.join("\n\n"))
+}
+
+fn surface_block(numbers: &str, name: &str, text: &str) -> String {
+    format!(
+        "surface{}\n{{\n{}// {}\n{}\n}}",
+        numbers,
+        INDENT,
+        name,
+        indent_lines(text)
+    )
+}
+
+fn indent_lines(text: &str) -> String {
+    text.trim_end_matches('\n')
+        .split('\n')
+        .map(|line| format!("{}{}", INDENT, line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::parse_yaml;
+
+    mod build_surfaces {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let case = include_str!("../test_target/test-001.yaml");
+            let root: Root = serde_yml::from_str(case).unwrap();
+
+            let result = build_surfaces(&root, &BuildOptions::new()).unwrap();
+            assert_eq!(result, include_str!("../test_target/test-001_surfaces.txt"));
+        }
+
+        #[test]
+        fn checking_value_with_template() {
+            let case = r#"
+characters:
+  - base: |
+      element0,overlay,body{{surface}}.png,0,0
+    parts:
+      - group: 目
+        details:
+          - name: 開
+            text: |
+              element1,overlay,eye.png,0,0
+          - name: 閉
+            text: |
+              // {{pose.目}}
+              animation{{surface * 10 + index}}.interval,never
+"#;
+            let root = parse_yaml(case).unwrap();
+
+            let result = build_surfaces(&root, &BuildOptions::new()).unwrap();
+            assert_eq!(
+                result,
+                r#"charset,UTF-8
+
+// 目
+
+surface1
+{
+  // 開
+  element1,overlay,eye.png,0,0
+}
+
+surface2
+{
+  // 閉
+  // 閉
+  animation22.interval,never
+}
+
+surface.append1
+{
+  element0,overlay,body1.png,0,0
+}
+
+surface.append2
+{
+  element0,overlay,body2.png,0,0
+}"#
+            );
+
+            let root = parse_yaml(&case.replace("pose.目", "pose.口")).unwrap();
+            assert!(matches!(
+                build_surfaces(&root, &BuildOptions::new()),
+                Err(ProcessError::Template { surface: 2, .. })
+            ));
+        }
+
+        #[test]
+        fn checking_value_with_whitelist() {
+            let case = include_str!("../test_target/test-001.yaml");
+            let root: Root = serde_yml::from_str(case).unwrap();
+
+            let options = BuildOptions::new().with_whitelist(vec![111, 1022]);
+            let result = build_surfaces(&root, &options).unwrap();
+            assert_eq!(
+                result,
+                r#"charset,UTF-8
+
+descript
+{
+  version,1
+}
+
+// 顔色
+
+surface111
+{
+  // 通常顔
+  
+}
+
+// 目
+
+surface111
+{
+  // こっち目
+  animation500300.interval,runonce+rarely
+  animation500300.option,shared-index
+  animation500300.pattern0,overlay,201,0,0,0
+  animation500300.pattern1,overlay,209,4000,0,0
+  animation500300.pattern2,overlay,204,100,0,0
+  animation500300.pattern3,overlay,201,100,0,0
+}
+
+// 腕
+
+surface111
+{
+  // 前手
+  animation505000.interval,runonce
+  animation505000.pattern0,overlay,503,0,0,0
+  
+  collisionex13,hand,polygon,288,423,330,410,336,414,341,422,328,446,317,436,288,442
+  collisionex14,hand,polygon,282,501,262,536,261,550,285,556,299,549,294,521,295,512
+}
+
+surface.append111-111
+{
+  collisionex10,shoulder,polygon,205,319,206,309,214,301,229,299,251,293,248,312
+  collisionex11,shoulder,polygon,292,293,309,299,332,302,339,315,301,315
+  collisionex7,mouse,ellipse,260,259,283,268
+  collisionex8,head,polygon,292,134,319,151,340,176,339,192,292,190,240,192,201,187,213,158,230,140,259,127
+  collisionex6,face,polygon,270,285,239,267,227,246,220,214,222,189,227,173,315,170,320,189,321,210,315,245,305,268
+  
+  //素体
+  element0,overlay,surface1000.png,0,0
+}
+
+
+// 素体
+
+surface1022
+{
+  // 腕上げ
+  animation500600.interval,runonce
+  animation500600.pattern0,overlay,101,0,0,0
+}
+
+// 目
+
+surface1022
+{
+  // あっち目
+  element0,overlay,surface3001.png,0,0
+}"#
+            );
+        }
+    }
+
+    mod write_surfaces {
+        use super::*;
+
+        use crate::line_ending::LineEnding;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let options = BuildOptions::new()
+                .with_line_ending(LineEnding::Crlf)
+                .with_bom(true);
+
+            let mut buffer = Vec::new();
+            write_surfaces(&root, &options, &mut buffer).unwrap();
+            let expected = build_surfaces(&root, &options)
+                .unwrap()
+                .replace('\n', "\r\n");
+            assert_eq!(&buffer[..3], b"\xEF\xBB\xBF");
+            assert_eq!(String::from_utf8(buffer[3..].to_vec()).unwrap(), expected);
+        }
+    }
+}
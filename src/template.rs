@@ -0,0 +1,301 @@
+use crate::{ast::Character, process::SurfaceNumber};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// Values of the variables for a generated surface.
+pub(crate) struct TemplateContext<'a> {
+    surface: usize,
+    character: usize,
+    /// Group, pose name and 1-based index of each part.
+    poses: Vec<(&'a str, &'a str, usize)>,
+    /// Index of the part whose text is being expanded, if any.
+    current: Option<usize>,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub(crate) fn new(
+        character_index: usize,
+        character: &'a Character,
+        surface: usize,
+        surface_number: &SurfaceNumber,
+    ) -> TemplateContext<'a> {
+        let poses = surface_number
+            .iter()
+            .zip(character.parts().iter())
+            .filter_map(|(part, group_data)| {
+                group_data
+                    .details()
+                    .get(part.number - 1)
+                    .map(|v| (group_data.group().as_str(), v.name().as_str(), part.number))
+            })
+            .collect();
+
+        TemplateContext {
+            surface,
+            character: character_index,
+            poses,
+            current: None,
+        }
+    }
+
+    /// Context for the text of the pose in the `index`-th group.
+    pub(crate) fn with_current(mut self, index: usize) -> TemplateContext<'a> {
+        self.current = Some(index);
+        self
+    }
+
+    fn pose(&self, group: &str) -> Result<&(&'a str, &'a str, usize), String> {
+        self.poses
+            .iter()
+            .find(|(v, _, _)| *v == group)
+            .ok_or_else(|| format!("unknown group: {}", group))
+    }
+
+    fn current(&self, name: &str) -> Result<&(&'a str, &'a str, usize), String> {
+        self.current
+            .and_then(|v| self.poses.get(v))
+            .ok_or_else(|| format!("{} is only available in the text of poses", name))
+    }
+
+    fn text(&self, name: &str) -> Result<String, String> {
+        if let Some(group) = name.strip_prefix("pose.") {
+            return Ok(self.pose(group)?.1.to_string());
+        }
+        match name {
+            "pose" => Ok(self.current(name)?.1.to_string()),
+            "group" => Ok(self.current(name)?.0.to_string()),
+            _ => self.number(name).map(|v| v.to_string()),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<i64, String> {
+        if let Some(group) = name.strip_prefix("index.") {
+            return Ok(self.pose(group)?.2 as i64);
+        }
+        match name {
+            "surface" => Ok(self.surface as i64),
+            "character" => Ok(self.character as i64),
+            "index" => Ok(self.current(name)?.2 as i64),
+            _ => Err(format!("unknown variable: {}", name)),
+        }
+    }
+}
+
+/// Whether `text` has variables to expand.
+pub(crate) fn has_variables(text: &str) -> bool {
+    text.contains(OPEN)
+}
+
+/// Replaces `{{...}}` in `text` with the values in `context`.
+pub(crate) fn expand(text: &str, context: &TemplateContext) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        result.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find(CLOSE)
+            .ok_or_else(|| format!("unclosed {}", OPEN))?;
+        let expression = rest[start + OPEN.len()..start + end].trim();
+        result.push_str(&evaluate(expression, context)?);
+
+        rest = &rest[start + end + CLOSE.len()..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Evaluates a variable, or an integer expression with `+ - * / %` and parentheses.
+fn evaluate(expression: &str, context: &TemplateContext) -> Result<String, String> {
+    let tokens = tokenize(expression)?;
+    if let [Token::Name(name)] = tokens.as_slice() {
+        return context.text(name);
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        context,
+    };
+    let value = parser.expression()?;
+    match parser.tokens.get(parser.position) {
+        Some(_) => Err(format!("unexpected token in {}", expression)),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(char),
+}
+
+const OPERATORS: [char; 7] = ['+', '-', '*', '/', '%', '(', ')'];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if OPERATORS.contains(&c) {
+            tokens.push(Token::Operator(c));
+            chars.next();
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || OPERATORS.contains(&c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                let number = word
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid number: {}", word))?;
+                tokens.push(Token::Number(number));
+            } else {
+                tokens.push(Token::Name(word));
+            }
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    context: &'a TemplateContext<'a>,
+}
+
+impl Parser<'_> {
+    fn next_operator(&mut self, operators: &[char]) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(c)) if operators.contains(c) => {
+                self.position += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expression(&mut self) -> Result<i64, String> {
+        let mut value = self.term()?;
+        while let Some(operator) = self.next_operator(&['+', '-']) {
+            let rhs = self.term()?;
+            value = match operator {
+                '+' => value.checked_add(rhs),
+                _ => value.checked_sub(rhs),
+            }
+            .ok_or("overflow")?;
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<i64, String> {
+        let mut value = self.factor()?;
+        while let Some(operator) = self.next_operator(&['*', '/', '%']) {
+            let rhs = self.factor()?;
+            value = match operator {
+                '*' => value.checked_mul(rhs).ok_or("overflow")?,
+                _ if rhs == 0 => return Err("division by zero".to_string()),
+                '/' => value.checked_div(rhs).ok_or("overflow")?,
+                _ => value.checked_rem(rhs).ok_or("overflow")?,
+            };
+        }
+        Ok(value)
+    }
+
+    fn factor(&mut self) -> Result<i64, String> {
+        if self.next_operator(&['-']).is_some() {
+            return self.factor()?.checked_neg().ok_or("overflow".to_string());
+        }
+        if self.next_operator(&['(']).is_some() {
+            let value = self.expression()?;
+            return match self.next_operator(&[')']) {
+                Some(_) => Ok(value),
+                None => Err("unclosed (".to_string()),
+            };
+        }
+
+        let token = self.tokens.get(self.position).ok_or("unexpected end")?;
+        self.position += 1;
+        match token {
+            Token::Number(v) => Ok(*v),
+            Token::Name(v) => self.context.number(v),
+            Token::Operator(c) => Err(format!("unexpected {}", c)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::{parse_yaml, SurfacePart};
+
+    mod expand {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+            let character = &root.characters()[0];
+            let surface_number = vec![
+                SurfacePart {
+                    number: 2,
+                    digits: 1,
+                },
+                SurfacePart {
+                    number: 1,
+                    digits: 1,
+                },
+                SurfacePart {
+                    number: 2,
+                    digits: 1,
+                },
+            ];
+            let context = TemplateContext::new(0, character, 212, &surface_number);
+
+            assert_eq!(
+                expand("{{surface}},{{ character }},{{pose.目}}", &context),
+                Ok("212,0,こっち目".to_string())
+            );
+            assert_eq!(
+                expand(
+                    "{{surface * 10 + 1}},{{(surface - 200) % 10}},{{-index.腕}}",
+                    &context
+                ),
+                Ok("2121,2,-2".to_string())
+            );
+            assert_eq!(
+                expand("{{group}}={{pose}}", &context.with_current(2)),
+                Ok("腕=胸に手".to_string())
+            );
+
+            let context = TemplateContext::new(0, character, 212, &surface_number);
+            assert!(expand("{{pose}}", &context).is_err());
+            assert!(expand("{{pose.口}}", &context).is_err());
+            assert!(expand("{{surface / 0}}", &context).is_err());
+            assert_eq!(
+                expand("{{(0 - 9223372036854775807 - 1) / (0 - 1)}}", &context),
+                Err("overflow".to_string())
+            );
+            assert_eq!(
+                expand("{{(0 - 9223372036854775807 - 1) % (0 - 1)}}", &context),
+                Err("overflow".to_string())
+            );
+            assert!(expand("{{surface + }}", &context).is_err());
+            assert!(expand("{{surface", &context).is_err());
+        }
+    }
+}
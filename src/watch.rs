@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use surfacetable_mixer::SurfaceEntry;
+
+use crate::{
+    command::{decide_outputs, generate, is_stdio, CommandError, Decisions},
+    config::Config,
+};
+
+/// Time to wait for more changes before rebuilding.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Rebuilds the outputs whenever the input files change, until interrupted.
+pub(crate) fn watch(config: &Config) -> Result<(), CommandError> {
+    if is_stdio(config.input()) {
+        return Err(notify::Error::generic("cannot watch stdin").into());
+    }
+
+    let decisions = decide_outputs(config)?;
+    let mut targets = watched_paths(config);
+
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mut directories = BTreeSet::new();
+    watch_directories(&mut watcher, &mut directories, &targets)?;
+
+    let mut previous = rebuild(config, &decisions, None);
+    eprintln!(
+        "watching {} (press Ctrl+C to stop).",
+        describe_paths(&targets)
+    );
+
+    loop {
+        let event = match receiver.recv() {
+            Ok(v) => v?,
+            Err(_) => return Ok(()),
+        };
+        if !is_relevant(&event, &targets) {
+            continue;
+        }
+
+        loop {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if let Some(surfaces) = rebuild(config, &decisions, previous.as_ref()) {
+            previous = Some(surfaces);
+        }
+
+        // Files stay watched once included, so that fixing a broken include is noticed.
+        let added: Vec<PathBuf> = watched_paths(config)
+            .into_iter()
+            .filter(|v| !targets.contains(v))
+            .collect();
+        if !added.is_empty() {
+            watch_directories(&mut watcher, &mut directories, &added)?;
+            eprintln!("watching {} too.", describe_paths(&added));
+            targets.extend(added);
+        }
+    }
+}
+
+/// Starts watching the directories of `targets` not watched yet.
+fn watch_directories(
+    watcher: &mut impl Watcher,
+    directories: &mut BTreeSet<PathBuf>,
+    targets: &[PathBuf],
+) -> Result<(), CommandError> {
+    for path in targets.iter() {
+        if let Some(directory) = path.parent() {
+            if directories.insert(directory.to_path_buf()) {
+                watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds once, printing errors instead of returning them.
+fn rebuild(
+    config: &Config,
+    decisions: &Decisions,
+    previous: Option<&Vec<SurfaceEntry>>,
+) -> Option<Vec<SurfaceEntry>> {
+    match generate(config, decisions) {
+        Ok(surfaces) => {
+            eprintln!("{}", summarize(previous, &surfaces));
+            Some(surfaces)
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            None
+        }
+    }
+}
+
+/// Input file, the files included from it, and the whitelist file.
+fn watched_paths(config: &Config) -> Vec<PathBuf> {
+    let mut paths = vec![config.input().to_path_buf()];
+    if let Ok(root) = config.input_format().read(config.input()) {
+        paths.extend(root.sources().iter().cloned());
+    }
+    if let Some(path) = config.whitelist_file() {
+        paths.push(path.clone());
+    }
+
+    let mut result: Vec<PathBuf> = Vec::new();
+    for path in paths.into_iter().map(|v| absolute_path(&v)) {
+        if !result.contains(&path) {
+            result.push(path);
+        }
+    }
+    result
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (path.parent(), path.file_name()) {
+        (Some(directory), Some(name)) => directory
+            .canonicalize()
+            .map(|v| v.join(name))
+            .unwrap_or(path),
+        _ => path,
+    }
+}
+
+fn is_relevant(event: &notify::Event, targets: &[PathBuf]) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+
+    event
+        .paths
+        .iter()
+        .any(|path| targets.contains(&absolute_path(path)))
+}
+
+fn describe_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|v| v.display().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn summarize(previous: Option<&Vec<SurfaceEntry>>, current: &[SurfaceEntry]) -> String {
+    let numbers = |surfaces: &[SurfaceEntry]| -> BTreeSet<(usize, usize)> {
+        surfaces
+            .iter()
+            .map(|v| (v.character(), v.number()))
+            .collect()
+    };
+    let describe = |numbers: Vec<&(usize, usize)>| {
+        numbers
+            .iter()
+            .map(|(character, number)| format!("\\{}:{}", character, number))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    let mut summary = format!("built {} surface(s).", current.len());
+    if let Some(previous) = previous {
+        let before = numbers(previous);
+        let after = numbers(current);
+
+        let added: Vec<&(usize, usize)> = after.difference(&before).collect();
+        let removed: Vec<&(usize, usize)> = before.difference(&after).collect();
+        if added.is_empty() && removed.is_empty() {
+            summary.push_str(" no surface numbers changed.");
+        }
+        if !added.is_empty() {
+            summary.push_str(&format!(" added: {}.", describe(added)));
+        }
+        if !removed.is_empty() {
+            summary.push_str(&format!(" removed: {}.", describe(removed)));
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use surfacetable_mixer::{list_surfaces, parse_yaml, BuildOptions};
+
+    fn entries(whitelist: Vec<usize>) -> Vec<SurfaceEntry> {
+        let root = parse_yaml(include_str!("../test_target/test-001.yaml")).unwrap();
+        list_surfaces(&root, &BuildOptions::new().with_whitelist(whitelist)).unwrap()
+    }
+
+    mod summarize {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let previous = entries(vec![111, 112, 1022]);
+
+            assert_eq!(summarize(None, &previous), "built 3 surface(s).");
+            assert_eq!(
+                summarize(Some(&previous), &previous),
+                "built 3 surface(s). no surface numbers changed."
+            );
+            assert_eq!(
+                summarize(Some(&previous), &entries(vec![111, 121, 1011])),
+                "built 3 surface(s). added: \\0:121, \\1:1011. removed: \\0:112, \\1:1022."
+            );
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{fmt, ops::RangeInclusive, path::Path, str::FromStr};
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    error::{ParseError, ProcessError},
+    process::read_file,
+};
+
+const COMMENT_PREFIX: char = '#';
+
+/// Surface numbers to output.
+///
+/// Tokens are separated by comma or whitespace, in the form of `[!][\N:]A[-B]`:
+/// `1011` for a surface, `1100-1199` for a range, `\1:` for surfaces of the character only,
+/// and `!` for excluding them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Whitelist {
+    entries: Vec<WhitelistEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct WhitelistEntry {
+    character: Option<usize>,
+    range: RangeInclusive<usize>,
+    excluded: bool,
+}
+
+/// Entry of a YAML whitelist, parsed while deserializing so that errors carry its location.
+struct WhitelistToken(WhitelistEntry);
+
+impl<'de> Deserialize<'de> for WhitelistToken {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TokenVisitor;
+
+        impl Visitor<'_> for TokenVisitor {
+            type Value = WhitelistToken;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a surface number or a whitelist token")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                let v = usize::try_from(v).map_err(E::custom)?;
+                Ok(WhitelistToken(WhitelistEntry {
+                    character: None,
+                    range: v..=v,
+                    excluded: false,
+                }))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                parse_token(v).map(WhitelistToken).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TokenVisitor)
+    }
+}
+
+impl From<Vec<usize>> for Whitelist {
+    fn from(value: Vec<usize>) -> Self {
+        Whitelist {
+            entries: value
+                .into_iter()
+                .map(|v| WhitelistEntry {
+                    character: None,
+                    range: v..=v,
+                    excluded: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Whitelist {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+
+        for token in s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+        {
+            entries.push(parse_token(token)?);
+        }
+
+        Ok(Whitelist { entries })
+    }
+}
+
+impl Whitelist {
+    /// Adds the entries of `other`.
+    pub fn extend(&mut self, other: Whitelist) {
+        self.entries.extend(other.entries);
+    }
+
+    /// Whether the surface of the character is output.
+    pub fn contains(&self, character_index: usize, number: usize) -> bool {
+        let matches = |entry: &&WhitelistEntry| {
+            entry.character.is_none_or(|v| v == character_index) && entry.range.contains(&number)
+        };
+
+        let mut included = self.entries.iter().filter(|v| !v.excluded).peekable();
+        (included.peek().is_none() || included.any(|v| matches(&v)))
+            && !self
+                .entries
+                .iter()
+                .filter(|v| v.excluded)
+                .any(|v| matches(&v))
+    }
+}
+
+fn parse_token(token: &str) -> Result<WhitelistEntry, String> {
+    let invalid = |reason: String| format!("Whitelist is invalid: token \"{}\": {}", token, reason);
+
+    let (body, excluded) = match token.strip_prefix('!') {
+        Some(v) => (v, true),
+        None => (token, false),
+    };
+
+    let (character, body) = match body.strip_prefix('\\') {
+        Some(v) => match v.split_once(':') {
+            Some((character, body)) => (
+                Some(
+                    character
+                        .parse::<usize>()
+                        .map_err(|e| invalid(format!("character: {}", e)))?,
+                ),
+                body,
+            ),
+            None => return Err(invalid("':' is required after the character".to_string())),
+        },
+        None => (None, body),
+    };
+
+    let range = match body.split_once('-') {
+        Some((start, end)) => {
+            let start = start.parse::<usize>().map_err(|e| invalid(e.to_string()))?;
+            let end = end.parse::<usize>().map_err(|e| invalid(e.to_string()))?;
+            if start > end {
+                return Err(invalid("start of the range is larger than end".to_string()));
+            }
+            start..=end
+        }
+        None => {
+            let v = body.parse::<usize>().map_err(|e| invalid(e.to_string()))?;
+            v..=v
+        }
+    };
+
+    Ok(WhitelistEntry {
+        character,
+        range,
+        excluded,
+    })
+}
+
+/// Reads a whitelist from a text file, or a YAML list if the extension is `yaml` or `yml`.
+///
+/// In text files, `#` starts a comment.
+pub fn read_whitelist(path: &Path) -> Result<Whitelist, ProcessError> {
+    let buffer = read_file(path)?;
+    let invalid = |line: usize, message: String| ProcessError::InvalidWhitelist {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let is_yaml = path
+        .extension()
+        .and_then(|v| v.to_str())
+        .is_some_and(|v| v.eq_ignore_ascii_case("yaml") || v.eq_ignore_ascii_case("yml"));
+
+    let mut whitelist = Whitelist::default();
+    if is_yaml {
+        let tokens = serde_yml::from_str::<Vec<WhitelistToken>>(&buffer)
+            .map_err(|e| ParseError::new(Some(path), &buffer, e))?;
+        whitelist.entries = tokens.into_iter().map(|v| v.0).collect();
+    } else {
+        for (index, line) in buffer.lines().enumerate() {
+            let line = line.split(COMMENT_PREFIX).next().unwrap_or_default();
+            let entries = Whitelist::from_str(line).map_err(|e| invalid(index + 1, e))?;
+            whitelist.extend(entries);
+        }
+    }
+
+    Ok(whitelist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod from_str {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let result = Whitelist::from_str("11,1100-1199 \\1:1011-1022,!1150,!\\0:12").unwrap();
+            assert_eq!(
+                result.entries,
+                vec![
+                    WhitelistEntry {
+                        character: None,
+                        range: 11..=11,
+                        excluded: false
+                    },
+                    WhitelistEntry {
+                        character: None,
+                        range: 1100..=1199,
+                        excluded: false
+                    },
+                    WhitelistEntry {
+                        character: Some(1),
+                        range: 1011..=1022,
+                        excluded: false
+                    },
+                    WhitelistEntry {
+                        character: None,
+                        range: 1150..=1150,
+                        excluded: true
+                    },
+                    WhitelistEntry {
+                        character: Some(0),
+                        range: 12..=12,
+                        excluded: true
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn failed_when_invalid_token() {
+            assert_eq!(
+                Whitelist::from_str("11,1a").unwrap_err(),
+                "Whitelist is invalid: token \"1a\": invalid digit found in string"
+            );
+            assert_eq!(
+                Whitelist::from_str("\\1-11").unwrap_err(),
+                "Whitelist is invalid: token \"\\1-11\": ':' is required after the character"
+            );
+            assert_eq!(
+                Whitelist::from_str("20-10").unwrap_err(),
+                "Whitelist is invalid: token \"20-10\": start of the range is larger than end"
+            );
+        }
+    }
+
+    mod contains {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let case = Whitelist::from_str("100-199,\\1:1011-1022,!150,!\\1:1012").unwrap();
+            assert!(case.contains(0, 100));
+            assert!(case.contains(1, 100));
+            assert!(!case.contains(0, 150));
+            assert!(!case.contains(0, 1011));
+            assert!(case.contains(1, 1011));
+            assert!(!case.contains(1, 1012));
+
+            let case = Whitelist::from_str("!12").unwrap();
+            assert!(case.contains(0, 11));
+            assert!(!case.contains(0, 12));
+        }
+    }
+
+    mod read_whitelist {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let result = read_whitelist(Path::new("test_target/test-001_whitelist.txt")).unwrap();
+            assert_eq!(
+                result,
+                Whitelist::from_str("111-122,!112,\\1:1011-1022").unwrap()
+            );
+
+            let result = read_whitelist(Path::new("test_target/test-001_whitelist.yaml")).unwrap();
+            assert_eq!(
+                result,
+                Whitelist::from_str("111,\\1:1011-1022,!1012").unwrap()
+            );
+        }
+
+        #[test]
+        fn failed_when_invalid_yaml_token() {
+            let path = Path::new("test_target/test-001_whitelist_invalid.yaml");
+            let result = read_whitelist(path).unwrap_err();
+            assert_eq!(
+                result.to_string(),
+                concat!(
+                    "test_target/test-001_whitelist_invalid.yaml:3:3: [2]: ",
+                    "Whitelist is invalid: token \"\\1:1022-1011\": start of the range is larger than end\n",
+                    "  |\n",
+                    "3 | - \"\\\\1:1022-1011\"\n",
+                    "  |   ^",
+                ),
+            );
+        }
+    }
+}